@@ -0,0 +1,197 @@
+//! Read fixed width binary records: kmer (little endian integer on 8 bytes if k ≤ 32 else 16 bytes) followed by count (little endian integer).
+//!
+//! Nucleotide encoding of kmer is declared by the order of nucleotides (value 0 to 3) and the bit order (first nucleotide in most or least significant bits), kmer are convert in [seq2bits](crate::seq2bits) encoding.
+
+/* std use */
+use std::io::Read;
+
+/* crate use */
+use anyhow::{Context, Result};
+
+/* local use */
+use crate::cli;
+use crate::error::Error;
+use crate::input;
+use crate::seq2bits;
+
+pub struct Reader<R: Read> {
+    input: R,
+    k: u8,
+    kmer_size: usize,
+    table: [u128; 4],
+    bit_order: cli::BitOrder,
+    record: Vec<u8>,
+    record_number: u64,
+}
+
+impl Reader<Box<dyn std::io::BufRead>> {
+    pub fn from_path(path: &str, k: u8, params: &cli::Command) -> Result<Self> {
+        Ok(Self::new(
+            input::open(path)?,
+            k,
+            &params.binary_nucleotide_order,
+            params.binary_bit_order,
+            params.binary_count_size,
+            params.encoding,
+        ))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(
+        input: R,
+        k: u8,
+        order: &cli::NucleotideOrder,
+        bit_order: cli::BitOrder,
+        count_size: usize,
+        encoding: seq2bits::Encoding,
+    ) -> Self {
+        let kmer_size = if k <= 32 { 8 } else { 16 };
+
+        let mut table = [0; 4];
+        for (value, nuc) in order.0.iter().enumerate() {
+            table[value] = encoding.nuc2bit(*nuc);
+        }
+
+        Self {
+            input,
+            k,
+            kmer_size,
+            table,
+            bit_order,
+            record: vec![0; kmer_size + count_size],
+            record_number: 0,
+        }
+    }
+
+    /// Convert kmer in declared encoding in seq2bits encoding
+    fn convert(&self, raw: u128) -> u128 {
+        let mut kmer = 0;
+
+        for i in 0..self.k as u32 {
+            let shift = match self.bit_order {
+                cli::BitOrder::Msb => 2 * (self.k as u32 - 1 - i),
+                cli::BitOrder::Lsb => 2 * i,
+            };
+
+            kmer = (kmer << 2) | self.table[((raw >> shift) & 0b11) as usize];
+        }
+
+        kmer
+    }
+
+    /// Read next record, return None at end of file
+    fn next_kmer(&mut self) -> Result<Option<(u128, u64)>> {
+        let mut readed = 0;
+        while readed < self.record.len() {
+            match self.input.read(&mut self.record[readed..]) {
+                Ok(0) if readed == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(Error::BinaryTruncatedRecord(self.record_number).into());
+                }
+                Ok(n) => readed += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).with_context(|| "Read binary record"),
+            }
+        }
+        self.record_number += 1;
+
+        let mut raw = 0;
+        for (i, byte) in self.record[..self.kmer_size].iter().enumerate() {
+            raw |= (*byte as u128) << (8 * i);
+        }
+
+        let mut count = 0;
+        for (i, byte) in self.record[self.kmer_size..].iter().enumerate() {
+            count |= (*byte as u64) << (8 * i);
+        }
+
+        Ok(Some((self.convert(raw), count)))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<(u128, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_kmer().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::str::FromStr;
+
+    const ACTG: seq2bits::Encoding = seq2bits::Encoding::ACTG;
+
+    fn read(
+        data: Vec<u8>,
+        k: u8,
+        order: &str,
+        bit_order: cli::BitOrder,
+        count_size: usize,
+    ) -> Result<Vec<(u128, u64)>> {
+        Reader::new(
+            std::io::Cursor::new(data),
+            k,
+            &cli::NucleotideOrder::from_str(order).unwrap(),
+            bit_order,
+            count_size,
+            ACTG,
+        )
+        .collect()
+    }
+
+    #[test]
+    fn msb() {
+        let mut data = Vec::new();
+        // ACGTA -> 0001101100 in ACGT order
+        data.extend(&0b0001101100u64.to_le_bytes());
+        data.extend(&3u32.to_le_bytes());
+        // TGGGG -> 1110101010 in ACGT order
+        data.extend(&0b1110101010u64.to_le_bytes());
+        data.extend(&300u32.to_le_bytes());
+
+        assert_eq!(
+            read(data, 5, "ACGT", cli::BitOrder::Msb, 4).unwrap(),
+            vec![(ACTG.seq2bit(b"ACGTA"), 3), (ACTG.seq2bit(b"TGGGG"), 300)]
+        );
+    }
+
+    #[test]
+    fn lsb() {
+        let mut data = Vec::new();
+        // ACGTA -> 0010110100 in ACTG order with first nucleotide in least significant bits
+        data.extend(&0b0010110100u64.to_le_bytes());
+        data.push(3);
+
+        assert_eq!(
+            read(data, 5, "ACTG", cli::BitOrder::Lsb, 1).unwrap(),
+            vec![(ACTG.seq2bit(b"ACGTA"), 3)]
+        );
+    }
+
+    #[test]
+    fn large_k() {
+        let seq = b"ACGTACGTACGTACGTACGTACGTACGTACGTACGTA";
+
+        let mut data = Vec::new();
+        data.extend(&ACTG.seq2bit(seq).to_le_bytes());
+        data.extend(&7u64.to_le_bytes());
+
+        assert_eq!(
+            read(data, seq.len() as u8, "ACTG", cli::BitOrder::Msb, 8).unwrap(),
+            vec![(ACTG.seq2bit(seq), 7)]
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let mut data = 0b0001101100u64.to_le_bytes().to_vec();
+        data.extend(&[3, 0]);
+
+        assert!(read(data, 5, "ACGT", cli::BitOrder::Msb, 4).is_err());
+    }
+}
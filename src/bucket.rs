@@ -1,130 +1,1232 @@
 /* std use */
-use std::io::Write;
 use std::str::FromStr;
 
 /* crate use */
 use anyhow::{Context, Result};
+use rand::{Rng, SeedableRng};
 
 /* local use */
+use crate::cli;
+use crate::error::Error;
+use crate::input;
 use crate::seq2bits;
 
-pub fn build(
+/// Counts of a kmer, one by sample
+pub type Counts = Vec<u64>;
+pub type Bucket = rustc_hash::FxHashMap<u128, Counts>;
+pub type Buckets = rustc_hash::FxHashMap<String, Bucket>;
+pub type Kmers<'a> = Box<dyn Iterator<Item = Result<(u128, Counts)>> + 'a>;
+
+/// Check records, length and nucleotides of kmers read in text input
+pub struct Validator {
+    pub k: Option<u8>,
+    policy: cli::InvalidNucleotide,
+    lenient: bool,
+    encoding: seq2bits::Encoding,
+    rng: rand::rngs::StdRng,
+    pub rejected: u64,
+    pub replaced: u64,
+    pub skipped: u64,
+}
+
+impl Validator {
+    pub fn new(
+        k: Option<u8>,
+        policy: cli::InvalidNucleotide,
+        lenient: bool,
+        encoding: seq2bits::Encoding,
+    ) -> Self {
+        Self {
+            k,
+            policy,
+            lenient,
+            encoding,
+            rng: rand::rngs::StdRng::seed_from_u64(42),
+            rejected: 0,
+            replaced: 0,
+            skipped: 0,
+        }
+    }
+
+    /// In lenient mode, error of a malformed record is logged and record is skipped
+    pub fn check<T>(&mut self, result: Result<Option<T>>) -> Result<Option<T>> {
+        match result {
+            Err(e) if self.lenient && is_malformed(&e) => {
+                log::warn!("Skip malformed record: {}", e);
+                self.skipped += 1;
+                Ok(None)
+            }
+            _ => result,
+        }
+    }
+
+    /// Convert kmer in 2 bit representation, return None if kmer is skipped
+    pub fn kmer(&mut self, seq: &str, line: u64, column: usize) -> Result<Option<u128>> {
+        let k = match self.k {
+            Some(k) => k,
+            None => *self.k.get_or_insert(cli::kmer_size(seq.len() as u64)?),
+        };
+        if seq.len() != k as usize {
+            return Err(Error::KmerLength {
+                line,
+                length: seq.len(),
+                k,
+            }
+            .into());
+        }
+
+        let position = match seq2bits::first_invalid(seq.as_bytes()) {
+            None => return Ok(Some(self.encoding.seq2bit(seq.as_bytes()))),
+            Some(position) => position,
+        };
+
+        match self.policy {
+            cli::InvalidNucleotide::Error => Err(Error::InvalidNucleotide {
+                nuc: seq[position..].chars().next().unwrap_or('?'),
+                line,
+                column,
+                position: position + 1,
+            }
+            .into()),
+            cli::InvalidNucleotide::SkipKmer => {
+                self.rejected += 1;
+                Ok(None)
+            }
+            cli::InvalidNucleotide::ReplaceRandom => {
+                self.replaced += 1;
+
+                let mut kmer = 0;
+                for nuc in seq.bytes() {
+                    let nuc = if seq2bits::is_valid(nuc) {
+                        nuc
+                    } else {
+                        b"ACGT"[self.rng.gen_range(0..4)]
+                    };
+
+                    kmer = (kmer << 2) | self.encoding.nuc2bit(nuc);
+                }
+
+                Ok(Some(kmer))
+            }
+        }
+    }
+
+    pub fn log_summary(&self) {
+        log::info!(
+            "{} kmers rejected and {} kmers with replaced nucleotides",
+            self.rejected,
+            self.replaced
+        );
+        log::info!("{} malformed lines skipped", self.skipped);
+    }
+}
+
+fn is_malformed(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<Error>(),
+        Some(Error::CsvRecord { .. })
+            | Some(Error::CsvMissingColumn { .. })
+            | Some(Error::CsvBadCount { .. })
+            | Some(Error::KmerLength { .. })
+    )
+}
+
+/// Merge counts of kmers present many times after canonicalization, in presence only mode counts are dropped
+pub struct Merger {
+    policy: cli::Duplicate,
+    presence_only: bool,
+    pub encoding: seq2bits::Encoding,
+    pub collisions: u64,
+}
+
+impl Merger {
+    pub fn new(policy: cli::Duplicate, presence_only: bool, encoding: seq2bits::Encoding) -> Self {
+        Self {
+            policy,
+            presence_only,
+            encoding,
+            collisions: 0,
+        }
+    }
+
+    /// Insert kmer in bucket, if kmer is already present counts are merged according to policy
+    pub fn insert(
+        &mut self,
+        bucket: &mut Bucket,
+        kmer: u128,
+        mut counts: Counts,
+        k: u8,
+    ) -> Result<()> {
+        if self.presence_only {
+            counts.clear();
+        }
+
+        let previous = match bucket.entry(kmer) {
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(counts);
+                return Ok(());
+            }
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        };
+
+        self.merge(previous, &counts, kmer, k)
+    }
+
+    /// Merge counts of a kmer already present according to policy
+    pub fn merge(&mut self, previous: &mut [u64], counts: &[u64], kmer: u128, k: u8) -> Result<()> {
+        self.collisions += 1;
+
+        match self.policy {
+            cli::Duplicate::Error => {
+                return Err(Error::DuplicateKmer(self.encoding.kmer2seq(kmer, k)).into())
+            }
+            cli::Duplicate::First => (),
+            cli::Duplicate::Sum => merge_counts(previous, counts, |a, b| a.saturating_add(b)),
+            cli::Duplicate::Max => merge_counts(previous, counts, std::cmp::max),
+            cli::Duplicate::Min => merge_counts(previous, counts, std::cmp::min),
+        }
+
+        Ok(())
+    }
+
+    pub fn log_summary(&self) {
+        log::info!("{} duplicate kmers are merged", self.collisions);
+    }
+}
+
+fn merge_counts<F>(previous: &mut [u64], counts: &[u64], f: F)
+where
+    F: Fn(u64, u64) -> u64,
+{
+    for (a, b) in previous.iter_mut().zip(counts) {
+        *a = f(*a, *b);
+    }
+}
+
+/// Read kmer and count in csv file, return kmer size and kmers
+pub fn csv<'a>(
     input: &str,
-    k: u8,
-    m: u8,
-    delimiter: u8,
+    params: &cli::Command,
+    validator: &'a mut Validator,
+) -> Result<(u8, impl Iterator<Item = Result<(u128, u64)>> + 'a)> {
+    csv_from_reader(input::open(input)?, params, validator)
+}
+
+pub fn csv_from_reader<'a, R: std::io::Read + 'a>(
+    input: R,
+    params: &cli::Command,
+    validator: &'a mut Validator,
+) -> Result<(u8, impl Iterator<Item = Result<(u128, u64)>> + 'a)> {
+    let (mut reader, headers) = csv_reader(input, params)?;
+
+    let kmer_column = column_index(&params.kmer_column, headers.as_ref())?;
+    let quantize = params.quantize.clone();
+    let count_columns = if params.presence_only {
+        Vec::new()
+    } else {
+        vec![column_index(&params.count_column, headers.as_ref())?]
+    };
+
+    // first record is read to get kmer size
+    let first = first_record(&mut reader, validator)?;
+    let k = infer_k(validator, first.as_ref(), kmer_column)?;
+
+    Ok((
+        k,
+        first
+            .into_iter()
+            .map(Ok)
+            .chain(reader.into_records())
+            .filter_map(move |record| {
+                let result =
+                    parse_record(record, kmer_column, &count_columns, &quantize, validator);
+
+                validator
+                    .check(result)
+                    .map(|kmer| {
+                        kmer.map(|(kmer, counts)| (kmer, counts.first().copied().unwrap_or(0)))
+                    })
+                    .transpose()
+            }),
+    ))
+}
+
+/// Read kmer and counts of many samples in csv file, each column except kmer column is a sample, return kmer size, sample names and kmers
+pub fn matrix<'a>(
+    input: &str,
+    params: &cli::Command,
+    validator: &'a mut Validator,
 ) -> Result<(
-    rustc_hash::FxHashSet<u128>,
-    rustc_hash::FxHashMap<String, rustc_hash::FxHashMap<u128, u8>>,
+    u8,
+    Vec<String>,
+    impl Iterator<Item = Result<(u128, Counts)>> + 'a,
 )> {
-    let mut bob = rustc_hash::FxHashSet::default();
-    let mut mini2kmers = rustc_hash::FxHashMap::default();
+    matrix_from_reader(input::open(input)?, params, validator)
+}
+
+pub fn matrix_from_reader<'a, R: std::io::Read + 'a>(
+    input: R,
+    params: &cli::Command,
+    validator: &'a mut Validator,
+) -> Result<(
+    u8,
+    Vec<String>,
+    impl Iterator<Item = Result<(u128, Counts)>> + 'a,
+)> {
+    let (mut reader, headers) = csv_reader(input, params)?;
 
-    let input =
-        std::io::BufReader::new(std::fs::File::open(input).with_context(|| "Open input file")?);
+    let kmer_column = column_index(&params.kmer_column, headers.as_ref())?;
+
+    // first record is read to get kmer size and, without header, number of column
+    let first = first_record(&mut reader, validator)?;
+    let k = infer_k(validator, first.as_ref(), kmer_column)?;
+    let nb_columns = match (headers.as_ref(), first.as_ref()) {
+        (Some(h), _) => h.len(),
+        (None, Some(record)) => record.len(),
+        (None, None) => 0,
+    };
+
+    let quantize = params.quantize.clone();
+    let count_columns = (0..nb_columns)
+        .filter(|i| *i != kmer_column)
+        .collect::<Vec<usize>>();
+    let names = count_columns
+        .iter()
+        .map(|i| match headers.as_ref() {
+            Some(h) => h[*i].to_string(),
+            None => format!("sample{}", i + 1),
+        })
+        .collect::<Vec<String>>();
+
+    Ok((
+        k,
+        names,
+        first
+            .into_iter()
+            .map(Ok)
+            .chain(reader.into_records())
+            .filter_map(move |record| {
+                let result =
+                    parse_record(record, kmer_column, &count_columns, &quantize, validator);
+
+                validator.check(result).transpose()
+            }),
+    ))
+}
+
+/// Read first record, in lenient mode malformed records are skipped
+fn first_record<R: std::io::Read>(
+    reader: &mut csv::Reader<R>,
+    validator: &mut Validator,
+) -> Result<Option<csv::StringRecord>> {
+    let mut record = csv::StringRecord::new();
+
+    loop {
+        let result = match reader.read_record(&mut record) {
+            Ok(true) => return Ok(Some(record)),
+            Ok(false) => return Ok(None),
+            Err(e) => Err(csv_error(e)),
+        };
+
+        validator.check::<()>(result)?;
+    }
+}
+
+fn csv_error(error: csv::Error) -> anyhow::Error {
+    Error::CsvRecord {
+        line: error.position().map(|p| p.line()).unwrap_or(0),
+        message: error.to_string(),
+    }
+    .into()
+}
+
+/// Without kmer size in validator, kmer size is the length of first kmer
+fn infer_k(
+    validator: &mut Validator,
+    first: Option<&csv::StringRecord>,
+    kmer_column: usize,
+) -> Result<u8> {
+    if let Some(k) = validator.k {
+        return Ok(k);
+    }
+
+    let k = cli::kmer_size(get_column(first.ok_or(Error::CantInferK)?, kmer_column)?.len() as u64)?;
+    log::info!("Kmer size {} is inferred from first kmer", k);
+    validator.k = Some(k);
+
+    Ok(k)
+}
+
+/// Parse kmer and counts of a csv record, return None if kmer is skipped
+fn parse_record(
+    record: csv::Result<csv::StringRecord>,
+    kmer_column: usize,
+    count_columns: &[usize],
+    quantize: &cli::Quantize,
+    validator: &mut Validator,
+) -> Result<Option<(u128, Counts)>> {
+    let record = record.map_err(csv_error)?;
+    let line = record.position().map(|p| p.line()).unwrap_or(0);
+
+    let mut counts = Vec::with_capacity(count_columns.len());
+    for column in count_columns {
+        let value = get_column(&record, *column)?;
+
+        counts.push(
+            parse_count(value, quantize).ok_or_else(|| Error::CsvBadCount {
+                line,
+                column: column + 1,
+                value: value.to_string(),
+            })?,
+        );
+    }
+
+    let kmer = match validator.kmer(get_column(&record, kmer_column)?, line, kmer_column + 1)? {
+        Some(kmer) => kmer,
+        None => return Ok(None),
+    };
+
+    Ok(Some((kmer, counts)))
+}
+
+/// Parse count, with a quantization count is a positive decimal
+fn parse_count(value: &str, quantize: &cli::Quantize) -> Option<u64> {
+    if let cli::Quantize::None = quantize {
+        return u64::from_str(value).ok();
+    }
+
+    let value = f64::from_str(value).ok()?;
+    if !(value >= 0.0 && value.is_finite()) {
+        return None;
+    }
+
+    Some(quantize_value(value, quantize))
+}
+
+/// Quantize a positive decimal in an integer, values upper than u64::MAX are saturated
+pub fn quantize_value(value: f64, quantize: &cli::Quantize) -> u64 {
+    match quantize {
+        cli::Quantize::None => value.round() as u64,
+        cli::Quantize::Linear(step) => (value / step).round() as u64,
+        cli::Quantize::Log(step) => (value.ln_1p() / step).round() as u64,
+        cli::Quantize::Bins(edges) => edges.iter().filter(|edge| **edge <= value).count() as u64,
+    }
+}
+
+/// Convert kmer with one count in kmer with counts of one sample
+pub fn single<I>(kmers: I) -> impl Iterator<Item = Result<(u128, Counts)>>
+where
+    I: Iterator<Item = Result<(u128, u64)>>,
+{
+    kmers.map(|result| result.map(|(kmer, count)| (kmer, vec![count])))
+}
+
+/// Maximal count of all kmers in all buckets
+pub fn max_count<'a, I>(buckets: I) -> u64
+where
+    I: Iterator<Item = &'a Bucket>,
+{
+    buckets
+        .flat_map(|bucket| bucket.values())
+        .flat_map(|counts| counts.iter())
+        .copied()
+        .max()
+        .unwrap_or(0)
+}
+
+/// Maximal value could be store on width bytes
+pub fn max_value(width: usize) -> u64 {
+    if width >= 8 {
+        u64::MAX
+    } else {
+        (1 << (8 * width)) - 1
+    }
+}
+
+/// Write counts in data as little endian integer of width bytes, counts upper than maximal value are saturated
+pub fn encode(counts: &[u64], width: usize, data: &mut Vec<u8>) {
+    let max = max_value(width);
+
+    for count in counts {
+        data.extend(&(*count).min(max).to_le_bytes()[..width]);
+    }
+}
+
+fn csv_reader<R: std::io::Read>(
+    input: R,
+    params: &cli::Command,
+) -> Result<(csv::Reader<R>, Option<csv::StringRecord>)> {
     let mut reader = csv::ReaderBuilder::new()
-        .delimiter(delimiter)
-        .has_headers(false)
+        .delimiter(params.delimiter as u8)
+        .has_headers(params.header)
+        .comment(params.comment.map(|c| c as u8))
         .from_reader(input);
-    let mut iter = reader.records();
 
-    while let Some(Ok(record)) = iter.next() {
-        let mut kmer = seq2bits::seq2bit(record[0].as_bytes());
-        let count = u8::from_str(&record[1])?;
+    let headers = if params.header {
+        Some(reader.headers().with_context(|| "Read csv header")?.clone())
+    } else {
+        None
+    };
+
+    Ok((reader, headers))
+}
+
+fn column_index(column: &cli::Column, headers: Option<&csv::StringRecord>) -> Result<usize> {
+    match column {
+        cli::Column::Index(index) => Ok(*index),
+        cli::Column::Name(name) => headers
+            .ok_or(Error::CsvColumnNameWithoutHeader)?
+            .iter()
+            .position(|x| x == name)
+            .ok_or_else(|| Error::CsvColumnNotInHeader(name.clone()).into()),
+    }
+}
+
+fn get_column(record: &csv::StringRecord, column: usize) -> Result<&str> {
+    record.get(column).ok_or_else(|| {
+        Error::CsvMissingColumn {
+            line: record.position().map(|p| p.line()).unwrap_or(0),
+            column: column + 1,
+        }
+        .into()
+    })
+}
+
+/// Assign each kmer to the bucket of its minimizer
+pub fn build<I>(
+    kmers: I,
+    k: u8,
+    m: u8,
+    merger: &mut Merger,
+) -> Result<(rustc_hash::FxHashSet<u128>, Buckets)>
+where
+    I: Iterator<Item = Result<(u128, Counts)>>,
+{
+    let mut bob = rustc_hash::FxHashSet::default();
+    let mut mini2kmers = rustc_hash::FxHashMap::default();
+
+    for result in kmers {
+        let (mut kmer, count) = result?;
 
-        let (minimizer, _, forward) = seq2bits::get_minimizer(kmer, k, m);
+        let (minimizer, _, forward) = merger.encoding.get_minimizer(kmer, k, m);
         kmer = if forward {
             kmer
         } else {
-            seq2bits::revcomp(kmer, k)
+            merger.encoding.revcomp(kmer, k)
         };
 
         if seq2bits::multiple_mini(kmer, minimizer, k, m) {
-            mini2kmers
-                .entry("multiple".to_string())
-                .or_insert_with(rustc_hash::FxHashMap::default)
-                .insert(kmer, count);
+            merger.insert(
+                mini2kmers
+                    .entry("multiple".to_string())
+                    .or_insert_with(rustc_hash::FxHashMap::default),
+                kmer,
+                count,
+                k,
+            )?;
         } else {
-            mini2kmers
-                .entry(format!("{}", minimizer))
-                .or_insert_with(rustc_hash::FxHashMap::default)
-                .insert(kmer, count);
+            merger.insert(
+                mini2kmers
+                    .entry(format!("{}", minimizer))
+                    .or_insert_with(rustc_hash::FxHashMap::default),
+                kmer,
+                count,
+                k,
+            )?;
             bob.insert(minimizer);
         }
     }
 
     Ok((bob, mini2kmers))
 }
-pub fn create(
-    input: &str,
-    prefix: &str,
+
+/// Merge buckets of an input in buckets of previous inputs, previous and current are the number of samples of previous inputs and of this input
+pub fn merge(
+    mini2kmers: &mut Buckets,
+    input: Buckets,
+    policy: cli::Merge,
+    previous: usize,
+    current: usize,
+) -> Result<()> {
+    if mini2kmers.is_empty() && previous == 0 {
+        *mini2kmers = input;
+        return Ok(());
+    }
+
+    match policy {
+        cli::Merge::Sum => {
+            if previous != current {
+                return Err(Error::InputSampleDiff {
+                    expected: previous,
+                    found: current,
+                }
+                .into());
+            }
+
+            for (mini, bucket) in input {
+                let kmers = mini2kmers.entry(mini).or_default();
+                for (kmer, counts) in bucket {
+                    match kmers.entry(kmer) {
+                        std::collections::hash_map::Entry::Vacant(entry) => {
+                            entry.insert(counts);
+                        }
+                        std::collections::hash_map::Entry::Occupied(mut entry) => {
+                            merge_counts(entry.get_mut(), &counts, |a, b| a.saturating_add(b))
+                        }
+                    }
+                }
+            }
+        }
+        cli::Merge::Samples => {
+            let mut input = input;
+
+            for (mini, kmers) in mini2kmers.iter_mut() {
+                let mut bucket = input.remove(mini).unwrap_or_default();
+
+                for (kmer, counts) in kmers.iter_mut() {
+                    match bucket.remove(kmer) {
+                        Some(other) => counts.extend(other),
+                        None => counts.resize(previous + current, 0),
+                    }
+                }
+
+                for (kmer, other) in bucket {
+                    kmers.insert(kmer, padded(other, previous));
+                }
+            }
+
+            for (mini, bucket) in input {
+                mini2kmers.insert(
+                    mini,
+                    bucket
+                        .into_iter()
+                        .map(|(kmer, other)| (kmer, padded(other, previous)))
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Counts of a new kmer with zero for previous samples
+fn padded(counts: Counts, previous: usize) -> Counts {
+    let mut padded = vec![0; previous];
+    padded.extend(counts);
+    padded
+}
+
+/// Merge kmers of inputs sorted in lexicographic order, each kmer is yield once and in lexicographic order
+///
+/// Only the next kmer of each input is store in memory. Duplicate kmers of an input are merged by merger, counts of a kmer present in many inputs are merged according to merge policy.
+pub struct SortedMerge<'a> {
+    inputs: Vec<Kmers<'a>>,
+    /// Lexicographic rank of last kmer read in each input
+    lasts: Vec<Option<u128>>,
+    /// Index of first count of each input in merged counts
+    offsets: Vec<usize>,
+    nb_samples: usize,
+    /// Next kmer of each input: rank, input index, kmer and counts
+    heads: std::collections::BinaryHeap<std::cmp::Reverse<(u128, usize, u128, Counts)>>,
+    pub merger: Merger,
     k: u8,
-    m: u8,
-    delimiter: u8,
-) -> Result<rustc_hash::FxHashSet<u128>> {
-    let mut bob = rustc_hash::FxHashSet::<u128>::default();
+}
 
-    let input =
-        std::io::BufReader::new(std::fs::File::open(input).with_context(|| "Open input file")?);
-    let mut reader = csv::ReaderBuilder::new()
-        .delimiter(delimiter)
-        .has_headers(false)
-        .from_reader(input);
-    let mut iter = reader.records();
+impl<'a> SortedMerge<'a> {
+    /// Build merge of inputs, each input is given with its number of samples
+    pub fn new(
+        inputs: Vec<(Kmers<'a>, usize)>,
+        policy: cli::Merge,
+        merger: Merger,
+        k: u8,
+    ) -> Result<Self> {
+        let mut kmers = Vec::with_capacity(inputs.len());
+        let mut offsets = Vec::with_capacity(inputs.len());
+        let mut nb_samples = 0;
 
-    while let Some(Ok(record)) = iter.next() {
-        let mut kmer = seq2bits::seq2bit(record[0].as_bytes());
-        let count = u8::from_str(&record[1])?;
+        for (input, samples) in inputs {
+            let samples = if merger.presence_only { 0 } else { samples };
 
-        let (minimizer, _, forward) = seq2bits::get_minimizer(kmer, k, m);
-        kmer = if forward {
-            kmer
-        } else {
-            seq2bits::revcomp(kmer, k)
+            match policy {
+                cli::Merge::Sum => {
+                    if !kmers.is_empty() && samples != nb_samples {
+                        return Err(Error::InputSampleDiff {
+                            expected: nb_samples,
+                            found: samples,
+                        }
+                        .into());
+                    }
+
+                    offsets.push(0);
+                    nb_samples = samples;
+                }
+                cli::Merge::Samples => {
+                    offsets.push(nb_samples);
+                    nb_samples += samples;
+                }
+            }
+
+            kmers.push(input);
+        }
+
+        let mut merge = Self {
+            lasts: vec![None; kmers.len()],
+            inputs: kmers,
+            offsets,
+            nb_samples,
+            heads: std::collections::BinaryHeap::new(),
+            merger,
+            k,
+        };
+        for i in 0..merge.inputs.len() {
+            merge.advance(i)?;
+        }
+
+        Ok(merge)
+    }
+
+    /// Read next kmer of an input, it must not be lower than previous kmer of this input
+    fn advance(&mut self, input: usize) -> Result<()> {
+        let (kmer, mut counts) = match self.inputs[input].next() {
+            Some(result) => result?,
+            None => return Ok(()),
         };
 
-        if seq2bits::multiple_mini(kmer, minimizer, k, m) {
-            write_kmer(kmer, count, &format!("{}multiple", prefix))?;
-        } else {
-            write_kmer(kmer, count, &format!("{}{}", prefix, minimizer))?;
-            bob.insert(minimizer);
+        let rank = self.merger.encoding.bit2acgt(kmer, self.k);
+        if matches!(self.lasts[input], Some(last) if rank < last) {
+            return Err(Error::UnsortedInput(self.merger.encoding.kmer2seq(kmer, self.k)).into());
+        }
+        self.lasts[input] = Some(rank);
+
+        if self.merger.presence_only {
+            counts.clear();
         }
+
+        self.heads
+            .push(std::cmp::Reverse((rank, input, kmer, counts)));
+
+        Ok(())
     }
 
-    Ok(bob)
-}
+    fn next_kmer(&mut self) -> Result<Option<(u128, Counts)>> {
+        let (rank, kmer) = match self.heads.peek() {
+            Some(std::cmp::Reverse((rank, _, kmer, _))) => (*rank, *kmer),
+            None => return Ok(None),
+        };
 
-pub fn write_kmer(kmer: u128, count: u8, filename: &str) -> Result<()> {
-    let mut bucket = if std::path::Path::new(filename).exists() {
-        std::fs::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(filename)
-            .with_context(|| "Open bucket file")?
-    } else {
-        std::fs::File::create(filename).with_context(|| "Open bucket file")?
-    };
+        // duplicates of an input are consecutive, heads with same rank are ordered by input
+        let mut parts: Vec<(usize, Counts)> = Vec::new();
+        while matches!(self.heads.peek(), Some(std::cmp::Reverse((next, ..))) if *next == rank) {
+            if let Some(std::cmp::Reverse((_, input, _, counts))) = self.heads.pop() {
+                self.advance(input)?;
 
-    writeln!(bucket, "{},{}", kmer, count).with_context(|| "Write kmer")?;
+                match parts.last_mut() {
+                    Some((last, previous)) if *last == input => {
+                        self.merger.merge(previous, &counts, kmer, self.k)?
+                    }
+                    _ => parts.push((input, counts)),
+                }
+            }
+        }
 
-    Ok(())
+        let mut merged = vec![0; self.nb_samples];
+        for (input, counts) in parts {
+            merge_counts(&mut merged[self.offsets[input]..], &counts, |a, b| {
+                a.saturating_add(b)
+            });
+        }
+
+        Ok(Some((kmer, merged)))
+    }
 }
 
-pub fn read(path: &str) -> Result<rustc_hash::FxHashMap<u128, u8>> {
-    let mut res = rustc_hash::FxHashMap::default();
+impl<'a> Iterator for SortedMerge<'a> {
+    type Item = Result<(u128, Counts)>;
 
-    let input = std::io::BufReader::new(
-        std::fs::File::open(path).with_context(|| format!("Read bucket {}", path))?,
-    );
-    let mut reader = csv::ReaderBuilder::new()
-        .delimiter(b',')
-        .has_headers(false)
-        .from_reader(input);
-    let mut iter = reader.records();
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_kmer().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use clap::Clap;
+
+    // same encoding as default parameters
+    const ACGT: seq2bits::Encoding = seq2bits::Encoding::ACGT;
+
+    fn read_csv(data: &[u8], args: &[&str]) -> Result<Vec<(u128, u64)>> {
+        let mut cli = vec![
+            "kmers2kff",
+            "-i",
+            "-",
+            "-o",
+            "-",
+            "-k",
+            "5",
+            "-m",
+            "3",
+            "-p",
+            ".",
+        ];
+        cli.extend(args);
+        let params = cli::Command::parse_from(cli);
+        let mut validator = Validator::new(
+            params.k,
+            params.invalid_nucleotide,
+            params.lenient,
+            params.encoding,
+        );
+
+        let kmers = csv_from_reader(std::io::Cursor::new(data.to_vec()), &params, &mut validator)?
+            .1
+            .collect();
+
+        kmers
+    }
+
+    #[test]
+    fn default_columns() {
+        assert_eq!(
+            read_csv(b"ACGTA,3\nTTTTT,4\n", &[]).unwrap(),
+            vec![(ACGT.seq2bit(b"ACGTA"), 3), (ACGT.seq2bit(b"TTTTT"), 4)]
+        );
+    }
 
-    while let Some(Ok(record)) = iter.next() {
-        res.insert(u128::from_str(&record[0])?, u8::from_str(&record[1])?);
+    #[test]
+    fn columns_index() {
+        assert_eq!(
+            read_csv(
+                b"1\tACGTA\tA\t3\n2\tTTTTT\tB\t4\n",
+                &["-d", "\t", "--kmer-column", "2", "--count-column", "4"]
+            )
+            .unwrap(),
+            vec![(ACGT.seq2bit(b"ACGTA"), 3), (ACGT.seq2bit(b"TTTTT"), 4)]
+        );
     }
 
-    Ok(res)
+    #[test]
+    fn columns_name() {
+        assert_eq!(
+            read_csv(
+                b"# a comment\nid,count,kmer\n1,3,ACGTA\n# other comment\n2,4,TTTTT\n",
+                &[
+                    "--header",
+                    "--comment",
+                    "#",
+                    "--kmer-column",
+                    "kmer",
+                    "--count-column",
+                    "count"
+                ]
+            )
+            .unwrap(),
+            vec![(ACGT.seq2bit(b"ACGTA"), 3), (ACGT.seq2bit(b"TTTTT"), 4)]
+        );
+    }
+
+    fn read_matrix(data: &[u8], args: &[&str]) -> Result<(Vec<String>, Vec<(u128, Counts)>)> {
+        let mut cli = vec![
+            "kmers2kff",
+            "-i",
+            "-",
+            "-o",
+            "-",
+            "-k",
+            "5",
+            "-m",
+            "3",
+            "-p",
+            ".",
+        ];
+        cli.extend(args);
+        let params = cli::Command::parse_from(cli);
+        let mut validator = Validator::new(
+            params.k,
+            params.invalid_nucleotide,
+            params.lenient,
+            params.encoding,
+        );
+
+        let (_, names, iter) =
+            matrix_from_reader(std::io::Cursor::new(data.to_vec()), &params, &mut validator)?;
+
+        let kmers = iter.collect::<Result<Vec<_>>>()?;
+
+        Ok((names, kmers))
+    }
+
+    #[test]
+    fn matrix_header() {
+        assert_eq!(
+            read_matrix(
+                b"a,kmer,b,c\n1,ACGTA,2,3\n4,TTTTT,5,6\n",
+                &["-f", "matrix", "--header", "--kmer-column", "kmer"]
+            )
+            .unwrap(),
+            (
+                vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                vec![
+                    (ACGT.seq2bit(b"ACGTA"), vec![1, 2, 3]),
+                    (ACGT.seq2bit(b"TTTTT"), vec![4, 5, 6])
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn matrix_no_header() {
+        assert_eq!(
+            read_matrix(b"ACGTA,1,2\nTTTTT,4,5\n", &["-f", "matrix"]).unwrap(),
+            (
+                vec!["sample2".to_string(), "sample3".to_string()],
+                vec![
+                    (ACGT.seq2bit(b"ACGTA"), vec![1, 2]),
+                    (ACGT.seq2bit(b"TTTTT"), vec![4, 5])
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn columns_error() {
+        assert!(read_csv(b"ACGTA,3\n", &["--kmer-column", "kmer"]).is_err());
+        assert!(read_csv(
+            b"kmer,count\nACGTA,3\n",
+            &["--header", "--kmer-column", "seq"]
+        )
+        .is_err());
+        assert!(read_csv(b"ACGTA,3\n", &["--count-column", "3"]).is_err());
+    }
+
+    #[test]
+    fn invalid_nucleotide() {
+        assert!(read_csv(b"ACGTA,3\nTTNTT,4\n", &[]).is_err());
+
+        assert_eq!(
+            read_csv(
+                b"ACGTA,3\nTTNTT,4\nTT1TT,4\n",
+                &["--invalid-nucleotide", "skip-kmer"]
+            )
+            .unwrap(),
+            vec![(ACGT.seq2bit(b"ACGTA"), 3)]
+        );
+
+        let kmers = read_csv(
+            b"acgta,3\nTTNTT,4\n",
+            &["--invalid-nucleotide", "replace-random"],
+        )
+        .unwrap();
+        assert_eq!(kmers[0], (ACGT.seq2bit(b"ACGTA"), 3));
+        assert_eq!(kmers[1].0 & 0b11_11_00_11_11, ACGT.seq2bit(b"TTATT"));
+    }
+
+    #[test]
+    fn malformed() {
+        let data = b"ACGTA,3\nTTTTT,four\nCCCCC\nACGTA,3,1\nGGGGG,5\n";
+
+        let error = read_csv(data, &[]).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::CsvBadCount {
+                line: 2,
+                column: 2,
+                ..
+            })
+        ));
+
+        assert_eq!(
+            read_csv(data, &["--lenient"]).unwrap(),
+            vec![(ACGT.seq2bit(b"ACGTA"), 3), (ACGT.seq2bit(b"GGGGG"), 5),]
+        );
+    }
+
+    #[test]
+    fn quantize() {
+        let data = b"ACGTA,0.26\nTTTTT,3\nCCCCC,12.5\n";
+
+        assert!(read_csv(data, &[]).is_err());
+        assert_eq!(
+            read_csv(data, &["--quantize", "linear:0.5"])
+                .unwrap()
+                .into_iter()
+                .map(|x| x.1)
+                .collect::<Vec<u64>>(),
+            vec![1, 6, 25]
+        );
+        assert_eq!(
+            read_csv(data, &["--quantize", "log:0.1"])
+                .unwrap()
+                .into_iter()
+                .map(|x| x.1)
+                .collect::<Vec<u64>>(),
+            vec![2, 14, 26]
+        );
+        assert_eq!(
+            read_csv(data, &["--quantize", "bins:0.5,3,10"])
+                .unwrap()
+                .into_iter()
+                .map(|x| x.1)
+                .collect::<Vec<u64>>(),
+            vec![0, 2, 3]
+        );
+        assert!(read_csv(b"ACGTA,-1.0\n", &["--quantize", "linear:0.5"]).is_err());
+    }
+
+    #[test]
+    fn kmer_length() {
+        assert!(read_csv(b"ACGTA,3\nTTTTTT,4\n", &[]).is_err());
+        assert!(read_csv(b"ACGT,3\n", &[]).is_err());
+
+        let params =
+            cli::Command::parse_from(&["kmers2kff", "-i", "-", "-o", "-", "-m", "3", "-p", "."]);
+        let mut validator = Validator::new(
+            params.k,
+            params.invalid_nucleotide,
+            params.lenient,
+            params.encoding,
+        );
+
+        let (k, iter) = csv_from_reader(
+            std::io::Cursor::new(b"ACGTAAC,3\nTTTTTTT,4\nAAA,1\n".to_vec()),
+            &params,
+            &mut validator,
+        )
+        .unwrap();
+        let kmers = iter.collect::<Vec<_>>();
+
+        assert_eq!(k, 7);
+        assert_eq!(kmers.len(), 3);
+        assert!(kmers[0].is_ok());
+        assert!(kmers[2].is_err());
+
+        // 300 nucleotides must not wrap to k = 44
+        let mut long = vec![b'A'; 300];
+        long.extend(b",3\n");
+        let mut validator = Validator::new(
+            params.k,
+            params.invalid_nucleotide,
+            params.lenient,
+            params.encoding,
+        );
+        assert!(csv_from_reader(std::io::Cursor::new(long), &params, &mut validator).is_err());
+        assert!(validator.kmer(&"A".repeat(300), 1, 1).is_err());
+    }
+
+    fn build_duplicate(policy: cli::Duplicate) -> Result<(u64, Vec<Counts>)> {
+        let kmers = vec![
+            (ACGT.seq2bit(b"ACGTA"), vec![3, 1]),
+            (ACGT.seq2bit(b"TACGT"), vec![2, 4]),
+            (ACGT.seq2bit(b"ACGTA"), vec![5, 0]),
+        ];
+        let mut merger = Merger::new(policy, false, ACGT);
+
+        let (_, mini2kmers) = build(kmers.into_iter().map(Ok), 5, 3, &mut merger)?;
+
+        Ok((
+            merger.collisions,
+            mini2kmers
+                .values()
+                .flat_map(|bucket| bucket.values())
+                .cloned()
+                .collect(),
+        ))
+    }
+
+    #[test]
+    fn duplicate() {
+        assert!(build_duplicate(cli::Duplicate::Error).is_err());
+        assert_eq!(
+            build_duplicate(cli::Duplicate::Sum).unwrap(),
+            (2, vec![vec![10, 5]])
+        );
+        assert_eq!(
+            build_duplicate(cli::Duplicate::Max).unwrap(),
+            (2, vec![vec![5, 4]])
+        );
+        assert_eq!(
+            build_duplicate(cli::Duplicate::Min).unwrap(),
+            (2, vec![vec![2, 0]])
+        );
+        assert_eq!(
+            build_duplicate(cli::Duplicate::First).unwrap(),
+            (2, vec![vec![3, 1]])
+        );
+    }
+
+    fn buckets(kmers: &[(&[u8], Counts)]) -> Buckets {
+        let mut merger = Merger::new(cli::Duplicate::Error, false, ACGT);
+
+        build(
+            kmers
+                .iter()
+                .map(|(seq, counts)| Ok((ACGT.seq2bit(seq), counts.clone()))),
+            5,
+            3,
+            &mut merger,
+        )
+        .unwrap()
+        .1
+    }
+
+    fn sorted(mini2kmers: Buckets) -> Vec<(u128, Counts)> {
+        let mut kmers = mini2kmers
+            .values()
+            .flat_map(|bucket| bucket.iter().map(|(kmer, counts)| (*kmer, counts.clone())))
+            .collect::<Vec<_>>();
+        kmers.sort();
+        kmers
+    }
+
+    #[test]
+    fn merge_() {
+        let mut mini2kmers = buckets(&[(b"ACGTA", vec![3]), (b"TTTTT", vec![4])]);
+        merge(
+            &mut mini2kmers,
+            buckets(&[(b"TACGT", vec![2]), (b"CCCCC", vec![1])]),
+            cli::Merge::Sum,
+            1,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sorted(mini2kmers),
+            sorted(buckets(&[
+                (b"ACGTA", vec![5]),
+                (b"TTTTT", vec![4]),
+                (b"CCCCC", vec![1])
+            ]))
+        );
+
+        let mut mini2kmers = buckets(&[(b"ACGTA", vec![3]), (b"TTTTT", vec![4])]);
+        merge(
+            &mut mini2kmers,
+            buckets(&[(b"TACGT", vec![2, 6]), (b"CCCCC", vec![1, 0])]),
+            cli::Merge::Samples,
+            1,
+            2,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sorted(mini2kmers),
+            sorted(buckets(&[
+                (b"ACGTA", vec![3, 2, 6]),
+                (b"TTTTT", vec![4, 0, 0]),
+                (b"CCCCC", vec![0, 1, 0])
+            ]))
+        );
+
+        let mut mini2kmers = buckets(&[(b"ACGTA", vec![3])]);
+        assert!(merge(
+            &mut mini2kmers,
+            buckets(&[(b"ACGTA", vec![3, 1])]),
+            cli::Merge::Sum,
+            1,
+            2
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn presence_only() {
+        let kmers = read_csv(b"ACGTA\nTTTTT\n", &["--presence-only"]).unwrap();
+        assert_eq!(
+            kmers,
+            vec![(ACGT.seq2bit(b"ACGTA"), 0), (ACGT.seq2bit(b"TTTTT"), 0),]
+        );
+
+        let mut merger = Merger::new(cli::Duplicate::Error, true, ACGT);
+        let (_, mini2kmers) = build(single(kmers.into_iter().map(Ok)), 5, 3, &mut merger).unwrap();
+        assert!(mini2kmers
+            .values()
+            .flat_map(|bucket| bucket.values())
+            .all(|counts| counts.is_empty()));
+    }
+
+    #[test]
+    fn encode_() {
+        let mut data = Vec::new();
+
+        encode(&[1, 300, 70000], 2, &mut data);
+        assert_eq!(data, vec![1, 0, 44, 1, 255, 255]);
+
+        data.clear();
+        encode(&[70000], 4, &mut data);
+        assert_eq!(data, 70000u32.to_le_bytes().to_vec());
+
+        assert_eq!(max_value(1), 255);
+        assert_eq!(max_value(8), u64::MAX);
+    }
+
+    fn sorted_kmers(seqs: &[(&str, Counts)]) -> Kmers<'static> {
+        Box::new(
+            seqs.iter()
+                .map(|(seq, counts)| Ok((ACGT.seq2bit(seq.as_bytes()), counts.clone())))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )
+    }
+
+    #[test]
+    fn sorted_merge() {
+        let inputs = vec![
+            (
+                sorted_kmers(&[("AAAAA", vec![1]), ("ACGTA", vec![2]), ("ACGTA", vec![3])]),
+                1,
+            ),
+            (sorted_kmers(&[("ACGTA", vec![4]), ("GGGGG", vec![5])]), 1),
+        ];
+
+        let merger = Merger::new(cli::Duplicate::Max, false, ACGT);
+        let mut merge = SortedMerge::new(inputs, cli::Merge::Samples, merger, 5).unwrap();
+
+        assert_eq!(
+            merge.by_ref().collect::<Result<Vec<_>>>().unwrap(),
+            vec![
+                (ACGT.seq2bit(b"AAAAA"), vec![1, 0]),
+                (ACGT.seq2bit(b"ACGTA"), vec![3, 4]),
+                (ACGT.seq2bit(b"GGGGG"), vec![0, 5]),
+            ]
+        );
+        assert_eq!(merge.merger.collisions, 1);
+
+        let inputs = vec![
+            (sorted_kmers(&[("ACGTA", vec![1])]), 1),
+            (sorted_kmers(&[("ACGTA", vec![4]), ("GGGGG", vec![5])]), 1),
+        ];
+        let merger = Merger::new(cli::Duplicate::Error, false, ACGT);
+        assert_eq!(
+            SortedMerge::new(inputs, cli::Merge::Sum, merger, 5)
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap(),
+            vec![
+                (ACGT.seq2bit(b"ACGTA"), vec![5]),
+                (ACGT.seq2bit(b"GGGGG"), vec![5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn sorted_merge_error() {
+        // G is lower than T in lexicographic order
+        let inputs = vec![(sorted_kmers(&[("AAAAT", vec![1]), ("AAAAG", vec![2])]), 1)];
+        let merger = Merger::new(cli::Duplicate::Sum, false, ACGT);
+        assert!(SortedMerge::new(inputs, cli::Merge::Sum, merger, 5)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .is_err());
+
+        let inputs = vec![
+            (sorted_kmers(&[("AAAAA", vec![1])]), 1),
+            (sorted_kmers(&[("AAAAA", vec![1, 2])]), 2),
+        ];
+        let merger = Merger::new(cli::Duplicate::Sum, false, ACGT);
+        assert!(SortedMerge::new(inputs, cli::Merge::Sum, merger, 5).is_err());
+    }
 }
@@ -1,5 +1,6 @@
 /* crate use */
 use crate::error::Error;
+use crate::seq2bits;
 
 #[derive(clap::Clap, Debug)]
 #[clap(
@@ -11,15 +12,42 @@ pub struct Command {
     #[clap(
         short = 'i',
         long = "input",
-        about = "Path of kmers counts in csv format"
+        about = "Path of kmers counts in csv format, '-' read stdin, gzip, bzip2, zstd and xz compression are detected, could be repeated to merge many inputs"
     )]
-    pub input: String,
+    pub input: Vec<String>,
 
-    #[clap(short = 'o', long = "output", about = "Path of the kff file")]
+    #[clap(
+        long = "input-list",
+        about = "File with a path of input on each line, inputs are merged"
+    )]
+    pub input_list: Option<String>,
+
+    #[clap(
+        long = "batch",
+        about = "Each input is convert in its own kff file, {name} in output is replaced by input name"
+    )]
+    pub batch: bool,
+
+    #[clap(
+        long = "merge",
+        about = "How counts of many inputs are merged: sum (counts of a kmer are summed) or samples (each input is a sample)",
+        default_value = "sum"
+    )]
+    pub merge: Merge,
+
+    #[clap(
+        short = 'o',
+        long = "output",
+        about = "Path of the kff file, - write on stdout"
+    )]
     pub output: String,
 
-    #[clap(short = 'k', long = "kmer-size", about = "Kmer size")]
-    pub k: u8,
+    #[clap(
+        short = 'k',
+        long = "kmer-size",
+        about = "Kmer size, if not set kmer size is the length of first kmer of csv input or kmer size of KMC or Jellyfish database"
+    )]
+    pub k: Option<u8>,
 
     #[clap(short = 'm', long = "minimizer-size", about = "Minimizer size")]
     pub m: u8,
@@ -32,22 +60,465 @@ pub struct Command {
     )]
     pub delimiter: char,
 
+    #[clap(long = "header", about = "First line of csv input is a header")]
+    pub header: bool,
+
+    #[clap(
+        long = "kmer-column",
+        about = "Column of kmer in csv input, 1-based index or name in header",
+        default_value = "1"
+    )]
+    pub kmer_column: Column,
+
+    #[clap(
+        long = "count-column",
+        about = "Column of count in csv input, 1-based index or name in header",
+        default_value = "2"
+    )]
+    pub count_column: Column,
+
+    #[clap(
+        long = "presence-only",
+        about = "Counts aren't stored (data_size is 0), csv input could contain only kmer column"
+    )]
+    pub presence_only: bool,
+
+    #[clap(
+        long = "comment",
+        about = "Lines of csv input begin by this character are ignored"
+    )]
+    pub comment: Option<char>,
+
+    #[clap(
+        short = 'f',
+        long = "format",
+        about = "Format of input: csv, matrix (csv with a count column by sample), kmc (path of KMC database), jellyfish (binary jellyfish database), fasta or fastq (kmers are counted), unitig (BCALM unitigs with abundance in header), gfa (GFA1 segments with KC or RC tag), kff (re-encode a kff file), binary (fixed width binary records)",
+        default_value = "csv"
+    )]
+    pub format: Format,
+
+    #[clap(
+        long = "lenient",
+        about = "Malformed lines of csv input are skipped instead of stopping with an error"
+    )]
+    pub lenient: bool,
+
+    #[clap(
+        long = "invalid-nucleotide",
+        about = "Policy when a kmer of csv input contains a nucleotide other than A, C, G or T: error, skip-kmer or replace-random",
+        default_value = "error"
+    )]
+    pub invalid_nucleotide: InvalidNucleotide,
+
+    #[clap(
+        long = "quantize",
+        about = "Counts of csv input are decimals quantized in integer: none, linear:STEP (count/STEP), log:STEP (ln(1+count)/STEP) or bins:E1,E2,... (number of bin edges lower or equal than count)",
+        default_value = "none"
+    )]
+    pub quantize: Quantize,
+
+    #[clap(
+        long = "count-width",
+        about = "Number of bytes used to store each count: 1, 2, 4 or 8",
+        default_value = "1"
+    )]
+    pub count_width: usize,
+
+    #[clap(
+        long = "overflow",
+        about = "Policy when a count is upper than count width capacity: error, saturate or auto-widen (smallest width able to store maximal count)",
+        default_value = "error"
+    )]
+    pub overflow: Overflow,
+
+    #[clap(
+        long = "duplicate",
+        about = "Policy when a kmer, or its reverse complement, is present many times: sum, max, min, first or error",
+        default_value = "sum"
+    )]
+    pub duplicate: Duplicate,
+
+    #[clap(
+        long = "min-abundance",
+        about = "With fasta or fastq input, kmers with a count lower than this value are ignored",
+        default_value = "1"
+    )]
+    pub min_abundance: u32,
+
+    #[clap(
+        long = "encoding",
+        about = "Nucleotides associate to value 0, 1, 2 and 3 in kff output and in memory",
+        default_value = "ACGT"
+    )]
+    pub encoding: seq2bits::Encoding,
+
+    #[clap(
+        long = "binary-nucleotide-order",
+        about = "With binary input, nucleotides associate to value 0, 1, 2 and 3",
+        default_value = "ACGT"
+    )]
+    pub binary_nucleotide_order: NucleotideOrder,
+
+    #[clap(
+        long = "binary-bit-order",
+        about = "With binary input, first nucleotide of kmer is in most (msb) or least (lsb) significant bits",
+        default_value = "msb"
+    )]
+    pub binary_bit_order: BitOrder,
+
+    #[clap(
+        long = "binary-count-size",
+        about = "With binary input, number of bytes of count: 1, 2, 4 or 8",
+        default_value = "8"
+    )]
+    pub binary_count_size: usize,
+
+    #[clap(
+        long = "metadata",
+        about = "Entry key=value add at the end of kff header metadata, could be repeated"
+    )]
+    pub metadata: Vec<MetadataEntry>,
+
+    #[clap(
+        long = "shards",
+        about = "Minimizer buckets are partitioned by hash of minimizer in this number of kff files, {shard} in output is replaced by shard number, a manifest with minimizer hash range of each shard is written",
+        default_value = "1"
+    )]
+    pub shards: usize,
+
+    #[clap(
+        long = "index",
+        about = "Write an index section (position of each section) and a footer (position of index and number of kmers) at the end of kff output"
+    )]
+    pub index: bool,
+
+    #[clap(
+        long = "streaming",
+        about = "Inputs are sorted in lexicographic order, kmers are read and written by window of --buffer-size kmers instead of storing all kmers in memory"
+    )]
+    pub streaming: bool,
+
+    #[clap(
+        long = "buffer-size",
+        about = "In streaming mode, number of kmers of a window",
+        default_value = "1000000"
+    )]
+    pub buffer_size: usize,
+
     #[clap(
         short = 'p',
         long = "prefix",
-        about = "Prefix add before temporary file"
+        about = "Deprecated and ignored, no temporary file is written, kept for compatibility with previous command lines"
     )]
-    pub prefix: String,
+    pub prefix: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Matrix,
+    Kmc,
+    Jellyfish,
+    Fasta,
+    Fastq,
+    Unitig,
+    Gfa,
+    Kff,
+    Binary,
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "matrix" => Ok(Format::Matrix),
+            "kmc" => Ok(Format::Kmc),
+            "jellyfish" => Ok(Format::Jellyfish),
+            "fasta" => Ok(Format::Fasta),
+            "fastq" => Ok(Format::Fastq),
+            "unitig" => Ok(Format::Unitig),
+            "gfa" => Ok(Format::Gfa),
+            "kff" => Ok(Format::Kff),
+            "binary" => Ok(Format::Binary),
+            _ => Err(Error::CliUnknowFormat(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Error,
+    Saturate,
+    AutoWiden,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Overflow::Error),
+            "saturate" => Ok(Overflow::Saturate),
+            "auto-widen" => Ok(Overflow::AutoWiden),
+            _ => Err(Error::CliUnknowOverflow(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Duplicate {
+    Error,
+    Sum,
+    Max,
+    Min,
+    First,
+}
+
+impl std::str::FromStr for Duplicate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Duplicate::Error),
+            "sum" => Ok(Duplicate::Sum),
+            "max" => Ok(Duplicate::Max),
+            "min" => Ok(Duplicate::Min),
+            "first" => Ok(Duplicate::First),
+            _ => Err(Error::CliUnknowDuplicate(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Quantize {
+    None,
+    Linear(f64),
+    Log(f64),
+    Bins(Vec<f64>),
+}
+
+impl std::str::FromStr for Quantize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || Error::CliUnknowQuantize(s.to_string());
+
+        let mut split = s.splitn(2, ':');
+        let scheme = split.next().unwrap_or("");
+        let value = split.next();
+
+        match (scheme, value) {
+            ("none", None) => Ok(Quantize::None),
+            ("linear", Some(step)) | ("log", Some(step)) => {
+                let step = step.parse::<f64>().map_err(|_| error())?;
+                if !(step > 0.0 && step.is_finite()) {
+                    return Err(error());
+                }
+
+                if scheme == "linear" {
+                    Ok(Quantize::Linear(step))
+                } else {
+                    Ok(Quantize::Log(step))
+                }
+            }
+            ("bins", Some(edges)) => {
+                let edges = edges
+                    .split(',')
+                    .map(|x| x.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| error())?;
+                if edges.iter().any(|x| !x.is_finite()) || edges.windows(2).any(|w| w[0] >= w[1]) {
+                    return Err(error());
+                }
+
+                Ok(Quantize::Bins(edges))
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+impl std::fmt::Display for Quantize {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Quantize::None => write!(f, "none"),
+            Quantize::Linear(step) => write!(f, "linear:{}", step),
+            Quantize::Log(step) => write!(f, "log:{}", step),
+            Quantize::Bins(edges) => write!(
+                f,
+                "bins:{}",
+                edges
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Merge {
+    Sum,
+    Samples,
+}
+
+impl std::str::FromStr for Merge {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Merge::Sum),
+            "samples" => Ok(Merge::Samples),
+            _ => Err(Error::CliUnknowMerge(s.to_string())),
+        }
+    }
+}
+
+/// Nucleotides associate to value 0, 1, 2 and 3 in a 2 bit encoding
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NucleotideOrder(pub [u8; 4]);
+
+impl std::str::FromStr for NucleotideOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+        let bytes = upper.as_bytes();
+
+        if bytes.len() != 4 || !b"ACGT".iter().all(|nuc| bytes.contains(nuc)) {
+            return Err(Error::CliUnknowNucleotideOrder(s.to_string()));
+        }
+
+        Ok(NucleotideOrder([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+impl std::str::FromStr for seq2bits::Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(seq2bits::Encoding::new(&NucleotideOrder::from_str(s)?.0))
+    }
+}
+
+/// Entry of kff header metadata set by user
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataEntry {
+    pub key: String,
+    pub value: String,
+}
+
+impl std::str::FromStr for MetadataEntry {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.splitn(2, '=');
+
+        match (split.next(), split.next()) {
+            (Some(key), Some(value)) if !key.is_empty() => Ok(MetadataEntry {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(Error::CliMetadataEntry(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOrder {
+    Msb,
+    Lsb,
+}
+
+impl std::str::FromStr for BitOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "msb" => Ok(BitOrder::Msb),
+            "lsb" => Ok(BitOrder::Lsb),
+            _ => Err(Error::CliUnknowBitOrder(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidNucleotide {
+    Error,
+    SkipKmer,
+    ReplaceRandom,
+}
+
+impl std::str::FromStr for InvalidNucleotide {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(InvalidNucleotide::Error),
+            "skip-kmer" => Ok(InvalidNucleotide::SkipKmer),
+            "replace-random" => Ok(InvalidNucleotide::ReplaceRandom),
+            _ => Err(Error::CliUnknowInvalidNucleotide(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl std::str::FromStr for Column {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(0) => Err(Error::CliColumnIndexZero),
+            Ok(index) => Ok(Column::Index(index - 1)),
+            Err(_) => Ok(Column::Name(s.to_string())),
+        }
+    }
 }
 
 pub fn check_params(params: Command) -> Result<Command, Error> {
-    if params.k > 64 {
-        Err(Error::CliKUpperThan64)
+    if let Some(k) = params.k {
+        check_k_m(k, params.m)?;
+    }
+
+    if params.input.is_empty() && params.input_list.is_none() {
+        Err(Error::CliNoInput)
     } else if params.m > 32 {
         Err(Error::CliMUpperThan32)
-    } else if params.m >= params.k {
-        Err(Error::CliMUpperOrEqualThanK)
+    } else if ![1, 2, 4, 8].contains(&params.count_width) {
+        Err(Error::CliCountWidth)
+    } else if ![1, 2, 4, 8].contains(&params.binary_count_size) {
+        Err(Error::CliBinaryCountSize)
+    } else if params.shards == 0 {
+        Err(Error::CliShardsZero)
     } else {
         Ok(params)
     }
 }
+
+/// Convert kmer size read in input, check it's lower or equal than 64 before narrowing
+pub fn kmer_size(length: u64) -> Result<u8, Error> {
+    if length > 64 {
+        Err(Error::CliKUpperThan64)
+    } else {
+        Ok(length as u8)
+    }
+}
+
+/// Check kmer size and minimizer size, kmer size could be set after input reading
+pub fn check_k_m(k: u8, m: u8) -> Result<(), Error> {
+    if k > 64 {
+        Err(Error::CliKUpperThan64)
+    } else if m >= k {
+        Err(Error::CliMUpperOrEqualThanK)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Count canonical kmers of sequences, kmers with a non ACGT nucleotide are ignored.
+
+/* crate use */
+use anyhow::Result;
+
+/* local use */
+use crate::fastx;
+use crate::seq2bits;
+
+/// Count canonical kmer of all records, kmer with count lower than min_abundance are removed
+pub fn count<I>(
+    records: I,
+    k: u8,
+    min_abundance: u32,
+    encoding: seq2bits::Encoding,
+) -> Result<Vec<(u128, u64)>>
+where
+    I: Iterator<Item = Result<fastx::Record>>,
+{
+    let mut counts: rustc_hash::FxHashMap<u128, u32> = rustc_hash::FxHashMap::default();
+
+    let mask = if k == 64 {
+        u128::MAX
+    } else {
+        (1 << (k as u32 * 2)) - 1
+    };
+
+    for result in records {
+        let record = result?;
+
+        let mut kmer: u128 = 0;
+        let mut length: usize = 0;
+        for nuc in record.seq {
+            if seq2bits::is_valid(nuc) {
+                kmer = ((kmer << 2) | encoding.nuc2bit(nuc)) & mask;
+                length += 1;
+
+                if length >= k as usize {
+                    let count = counts.entry(encoding.canonical(kmer, k).0).or_insert(0);
+                    *count = count.saturating_add(1);
+                }
+            } else {
+                length = 0;
+            }
+        }
+    }
+
+    log::info!("{} distinct kmers counted", counts.len());
+
+    let kmers: Vec<(u128, u64)> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= min_abundance)
+        .map(|(kmer, count)| (kmer, count as u64))
+        .collect();
+
+    log::info!(
+        "{} kmers with abundance upper or equal than {}",
+        kmers.len(),
+        min_abundance
+    );
+
+    Ok(kmers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTG: seq2bits::Encoding = seq2bits::Encoding::ACTG;
+
+    fn records(seqs: &[&[u8]]) -> Vec<Result<fastx::Record>> {
+        seqs.iter()
+            .map(|seq| {
+                Ok(fastx::Record {
+                    header: Vec::new(),
+                    seq: seq.to_vec(),
+                })
+            })
+            .collect()
+    }
+
+    fn canonical(seq: &[u8]) -> u128 {
+        ACTG.canonical(ACTG.seq2bit(seq), 5).0
+    }
+
+    #[test]
+    fn count_() {
+        let mut kmers = count(
+            records(&[b"ACGTTAC", b"gtaacNACGTT"]).into_iter(),
+            5,
+            1,
+            ACTG,
+        )
+        .unwrap();
+        kmers.sort();
+
+        let mut truth = vec![
+            (canonical(b"ACGTT"), 2),
+            (canonical(b"CGTTA"), 1),
+            (canonical(b"GTTAC"), 2),
+        ];
+        truth.sort();
+
+        assert_eq!(kmers, truth);
+    }
+
+    #[test]
+    fn min_abundance() {
+        let kmers = count(records(&[b"ACGTTAC", b"ACGTT"]).into_iter(), 5, 2, ACTG).unwrap();
+
+        assert_eq!(kmers, vec![(canonical(b"ACGTT"), 2)]);
+    }
+
+    #[test]
+    fn large_count() {
+        let seq = vec![b'A'; 300];
+
+        let kmers = count(records(&[&seq]).into_iter(), 5, 1, ACTG).unwrap();
+
+        assert_eq!(kmers, vec![(canonical(b"AAAAA"), 296)]);
+    }
+}
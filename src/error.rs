@@ -11,4 +11,174 @@ pub enum Error {
 
     #[error("Minimizer size is upper than 32")]
     CliMUpperThan32,
+
+    #[error("Input format {0} isn't supported")]
+    CliUnknowFormat(String),
+
+    #[error("Column index start at 1")]
+    CliColumnIndexZero,
+
+    #[error("Column {0} isn't present in csv header")]
+    CsvColumnNotInHeader(String),
+
+    #[error("Column is select by name but csv input have no header")]
+    CsvColumnNameWithoutHeader,
+
+    #[error("Line {line} have no column {column}")]
+    CsvMissingColumn { line: u64, column: usize },
+
+    #[error("Kmer size of input ({input}) is different than kmer size parameter ({param})")]
+    InputKDiffThanParams { input: u64, param: u8 },
+
+    #[error("Kmer size must be set with this input format")]
+    CliKRequired,
+
+    #[error("Kmer size can't be infer from an empty input")]
+    CantInferK,
+
+    #[error("Csv record at line {line} is malformed: {message}")]
+    CsvRecord { line: u64, message: String },
+
+    #[error("Count '{value}' at line {line}, column {column} isn't a positive integer")]
+    CsvBadCount {
+        line: u64,
+        column: usize,
+        value: String,
+    },
+
+    #[error("Kmer at line {line} have length {length} but kmer size is {k}")]
+    KmerLength { line: u64, length: usize, k: u8 },
+
+    #[error("Quantization {0} isn't supported, use none, linear:STEP, log:STEP or bins:E1,E2,... with increasing edges")]
+    CliUnknowQuantize(String),
+
+    #[error("Count width must be 1, 2, 4 or 8")]
+    CliCountWidth,
+
+    #[error("Overflow policy {0} isn't supported")]
+    CliUnknowOverflow(String),
+
+    #[error("Invalid nucleotide policy {0} isn't supported")]
+    CliUnknowInvalidNucleotide(String),
+
+    #[error(
+        "Invalid nucleotide '{nuc}' at line {line}, column {column}, position {position} of kmer"
+    )]
+    InvalidNucleotide {
+        nuc: char,
+        line: u64,
+        column: usize,
+        position: usize,
+    },
+
+    #[error("Count {count} can't be store on {width} bytes")]
+    CountOverflow { count: u64, width: usize },
+
+    #[error("At least one input must be set with -i or --input-list")]
+    CliNoInput,
+
+    #[error("In batch mode output must contain {{name}}, it's replaced by name of each input")]
+    CliBatchOutputTemplate,
+
+    #[error("In batch mode inputs {first} and {second} have the same output {output}")]
+    BatchSameOutput {
+        first: String,
+        second: String,
+        output: String,
+    },
+
+    #[error("Number of shards must be upper than 0")]
+    CliShardsZero,
+
+    #[error(
+        "With many shards output must contain {{shard}}, it's replaced by number of each shard"
+    )]
+    CliShardOutputTemplate,
+
+    #[error("Streaming mode doesn't support auto-widen overflow policy, count width must be known before reading kmers")]
+    CliStreamingAutoWiden,
+
+    #[error("Kmer {0} is lower than previous kmer of its input, streaming mode require inputs sorted in lexicographic order")]
+    UnsortedInput(String),
+
+    #[error("Merge policy {0} isn't supported")]
+    CliUnknowMerge(String),
+
+    #[error("Input have {found} samples but previous inputs have {expected} samples, counts can't be summed")]
+    InputSampleDiff { expected: usize, found: usize },
+
+    #[error("Duplicate policy {0} isn't supported")]
+    CliUnknowDuplicate(String),
+
+    #[error("Kmer {0} is present many times (in same or reverse complement orientation)")]
+    DuplicateKmer(String),
+
+    #[error("KMC database marker is missing or corrupted")]
+    KmcBadMarker,
+
+    #[error("KMC database header is truncated or corrupted")]
+    KmcBadHeader,
+
+    #[error("KMC database version {0:#x} isn't supported")]
+    KmcUnsupportedVersion(u32),
+
+    #[error("KMC database in quality-aware mode isn't supported")]
+    KmcQualityMode,
+
+    #[error("Jellyfish header is missing or corrupted")]
+    JellyfishBadHeader,
+
+    #[error("Jellyfish format {0} isn't supported, only binary/sorted is supported")]
+    JellyfishUnsupportedFormat(String),
+
+    #[error("Jellyfish record {0} is truncated")]
+    JellyfishTruncatedRecord(u64),
+
+    #[error("Nucleotide order {0} isn't a permutation of ACGT")]
+    CliUnknowNucleotideOrder(String),
+
+    #[error("Metadata entry {0} isn't in key=value format")]
+    CliMetadataEntry(String),
+
+    #[error("Bit order {0} isn't supported")]
+    CliUnknowBitOrder(String),
+
+    #[error("Binary count size must be 1, 2, 4 or 8")]
+    CliBinaryCountSize,
+
+    #[error("Binary record {0} is truncated")]
+    BinaryTruncatedRecord(u64),
+
+    #[error("KFF count width {0} isn't 1, 2, 4 or 8")]
+    KffCountWidth(String),
+
+    #[error("KFF data size {data_size} isn't a multiple of count width {width}")]
+    KffDataSize { data_size: usize, width: usize },
+
+    #[error("Sequence record malformed at line {0}")]
+    FastxBadRecord(u64),
+
+    #[error("Unitig {0} have no abundance (km:f: or ab:Z: field)")]
+    UnitigMissingAbundance(String),
+
+    #[error("Unitig {header} have {found} abundances but {expected} kmers")]
+    UnitigAbundanceLength {
+        header: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("GFA line {0} is malformed")]
+    GfaBadLine(u64),
+
+    #[error("GFA segment {0} have no sequence")]
+    GfaMissingSequence(String),
+
+    #[error("GFA segment {0} have no abundance (KC:i: or RC:i: tag)")]
+    GfaMissingAbundance(String),
+
+    #[error(
+        "GFA link at line {line} have overlap {overlap} but kmer size is {k} (overlap must be k-1)"
+    )]
+    GfaOverlap { line: u64, overlap: String, k: u8 },
 }
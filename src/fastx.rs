@@ -0,0 +1,161 @@
+//! A minimal FASTA and FASTQ parser, format is detected on first character of each record.
+//! FASTA sequence could be split on many lines, FASTQ record must be on four lines.
+
+/* std use */
+use std::io::BufRead;
+
+/* crate use */
+use anyhow::{Context, Result};
+
+/* local use */
+use crate::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub header: Vec<u8>,
+    pub seq: Vec<u8>,
+}
+
+pub struct Reader<R: BufRead> {
+    input: R,
+    line: Vec<u8>,
+    line_number: u64,
+    next_header: Option<Vec<u8>>,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            line: Vec::new(),
+            line_number: 0,
+            next_header: None,
+        }
+    }
+
+    /// Read a line in self.line without end of line, return false at end of file
+    fn read_line(&mut self) -> Result<bool> {
+        self.line.clear();
+        let readed = self
+            .input
+            .read_until(b'\n', &mut self.line)
+            .with_context(|| format!("Read sequence file line {}", self.line_number + 1))?;
+        self.line_number += 1;
+
+        while self.line.last() == Some(&b'\n') || self.line.last() == Some(&b'\r') {
+            self.line.pop();
+        }
+
+        Ok(readed != 0)
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        let header = if let Some(header) = self.next_header.take() {
+            header
+        } else {
+            loop {
+                if !self.read_line()? {
+                    return Ok(None);
+                }
+                if !self.line.is_empty() {
+                    break self.line.clone();
+                }
+            }
+        };
+
+        match header.first() {
+            Some(b'>') => {
+                let mut seq = Vec::new();
+                while self.read_line()? {
+                    if self.line.first() == Some(&b'>') {
+                        self.next_header = Some(self.line.clone());
+                        break;
+                    }
+                    seq.extend(&self.line);
+                }
+
+                Ok(Some(Record {
+                    header: header[1..].to_vec(),
+                    seq,
+                }))
+            }
+            Some(b'@') => {
+                if !self.read_line()? {
+                    return Err(Error::FastxBadRecord(self.line_number).into());
+                }
+                let seq = self.line.clone();
+
+                if !self.read_line()? || self.line.first() != Some(&b'+') {
+                    return Err(Error::FastxBadRecord(self.line_number).into());
+                }
+                if !self.read_line()? || self.line.len() != seq.len() {
+                    return Err(Error::FastxBadRecord(self.line_number).into());
+                }
+
+                Ok(Some(Record {
+                    header: header[1..].to_vec(),
+                    seq,
+                }))
+            }
+            _ => Err(Error::FastxBadRecord(self.line_number).into()),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn records(data: &[u8]) -> Result<Vec<Record>> {
+        Reader::new(std::io::Cursor::new(data.to_vec())).collect()
+    }
+
+    #[test]
+    fn fasta() {
+        assert_eq!(
+            records(b">1 first\nACGT\nTTGA\n\n>2\r\nCCCC\r\n").unwrap(),
+            vec![
+                Record {
+                    header: b"1 first".to_vec(),
+                    seq: b"ACGTTTGA".to_vec()
+                },
+                Record {
+                    header: b"2".to_vec(),
+                    seq: b"CCCC".to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fastq() {
+        assert_eq!(
+            records(b"@1\nACGT\n+\nIIII\n@2\nCC\n+2\nII").unwrap(),
+            vec![
+                Record {
+                    header: b"1".to_vec(),
+                    seq: b"ACGT".to_vec()
+                },
+                Record {
+                    header: b"2".to_vec(),
+                    seq: b"CC".to_vec()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(records(b"ACGT\n").is_err());
+        assert!(records(b"@1\nACGT\nIIII\n").is_err());
+        assert!(records(b"@1\nACGT\n+\nII\n").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Read segments of a compacted de Bruijn graph in GFA1 format.
+//!
+//! Each segment is decomposed in kmers, abundance of kmers is the segment `KC:i:` (kmer count) or, if absent, `RC:i:` (read count) divided by number of kmers of segment. Overlap of links must be equal to k-1.
+
+/* std use */
+use std::io::BufRead;
+
+/* crate use */
+use anyhow::{Context, Result};
+
+/* local use */
+use crate::error::Error;
+use crate::seq2bits;
+use crate::unitig;
+
+/// Kmers and abundance of a GFA line, links are only checked
+pub fn parse_line(
+    line: &str,
+    line_number: u64,
+    k: u8,
+    encoding: seq2bits::Encoding,
+) -> Result<Vec<(u128, u64)>> {
+    let mut fields = line.split('\t');
+
+    match fields.next() {
+        Some("S") => {
+            let name = fields.next().unwrap_or("");
+            let seq = fields
+                .next()
+                .ok_or(Error::GfaBadLine(line_number))?
+                .as_bytes();
+            if seq == b"*" {
+                return Err(Error::GfaMissingSequence(name.to_string()).into());
+            }
+
+            let mut kc = None;
+            let mut rc = None;
+            for tag in fields {
+                if let Some(value) = tag.strip_prefix("KC:i:") {
+                    kc = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| Error::GfaBadLine(line_number))?,
+                    );
+                } else if let Some(value) = tag.strip_prefix("RC:i:") {
+                    rc = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| Error::GfaBadLine(line_number))?,
+                    );
+                }
+            }
+            let total = kc
+                .or(rc)
+                .ok_or_else(|| Error::GfaMissingAbundance(name.to_string()))?;
+
+            let nb_kmers = (seq.len() + 1).saturating_sub(k as usize) as u64;
+            let mean = if nb_kmers == 0 {
+                0
+            } else {
+                (total as f64 / nb_kmers as f64).round() as u64
+            };
+
+            Ok(unitig::split(
+                seq,
+                k,
+                &unitig::Abundance::Mean(mean),
+                encoding,
+            ))
+        }
+        Some("L") => {
+            let overlap = fields.nth(4).ok_or(Error::GfaBadLine(line_number))?;
+
+            if overlap != "*" {
+                let length = overlap
+                    .strip_suffix('M')
+                    .and_then(|x| x.parse::<u64>().ok())
+                    .ok_or(Error::GfaBadLine(line_number))?;
+
+                if length != k as u64 - 1 {
+                    return Err(Error::GfaOverlap {
+                        line: line_number,
+                        overlap: overlap.to_string(),
+                        k,
+                    }
+                    .into());
+                }
+            }
+
+            Ok(Vec::new())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Kmers and abundance of all segments
+pub fn kmers<R: BufRead>(
+    input: R,
+    k: u8,
+    encoding: seq2bits::Encoding,
+) -> impl Iterator<Item = Result<(u128, u64)>> {
+    input.lines().enumerate().flat_map(move |(i, line)| {
+        match line
+            .with_context(|| format!("Read GFA line {}", i + 1))
+            .and_then(|line| parse_line(&line, i as u64 + 1, k, encoding))
+        {
+            Ok(kmers) => kmers.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(e) => vec![Err(e)],
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTG: seq2bits::Encoding = seq2bits::Encoding::ACTG;
+
+    fn read(data: &[u8]) -> Result<Vec<(u128, u64)>> {
+        kmers(std::io::Cursor::new(data.to_vec()), 5, ACTG).collect()
+    }
+
+    #[test]
+    fn segment() {
+        assert_eq!(
+            read(b"H\tVN:Z:1.0\nS\t1\tACGTTAC\tLN:i:7\tKC:i:7\nS\t2\tTTTTT\tRC:i:4\n").unwrap(),
+            vec![
+                (ACTG.seq2bit(b"ACGTT"), 2),
+                (ACTG.seq2bit(b"CGTTA"), 2),
+                (ACTG.seq2bit(b"GTTAC"), 2),
+                (ACTG.seq2bit(b"TTTTT"), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn link() {
+        assert!(read(b"S\t1\tACGTTAC\tKC:i:7\nL\t1\t+\t1\t-\t4M\n").is_ok());
+        assert!(read(b"S\t1\tACGTTAC\tKC:i:7\nL\t1\t+\t1\t-\t3M\n").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(read(b"S\t1\tACGTTAC\n").is_err());
+        assert!(read(b"S\t1\t*\tKC:i:7\n").is_err());
+        assert!(read(b"S\t1\n").is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Track position of sections in kff output to write KFF index and footer sections.
+//!
+//! Index section is write after all kmers sections, it contains type and position (relative to the end of index section) of each section. Minimizer of each minimizer section is recorded in a variables section written just before index section, a variable `minimizer_{minimizer sequence}_{n}` store absolute position of the n-th (0-based) section of this minimizer, so a reader could seek directly to bucket of a minimizer. Footer is a variables section with absolute position of index section (`first_index`), total number of kmers (`nb_kmers`) and size of footer (`footer_size`, the last variable).
+
+/* std use */
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/* crate use */
+use anyhow::Result;
+
+/// Output shared between kff writer and index, count number of bytes written
+pub struct Output<W: Write> {
+    inner: Rc<RefCell<(W, u64)>>,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(inner: W) -> Self {
+        Output {
+            inner: Rc::new(RefCell::new((inner, 0))),
+        }
+    }
+
+    /// Number of bytes written
+    pub fn position(&self) -> u64 {
+        self.inner.borrow().1
+    }
+}
+
+impl<W: Write> Clone for Output<W> {
+    fn clone(&self) -> Self {
+        Output {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+
+        let written = inner.0.write(buf)?;
+        inner.1 += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.borrow_mut().0.flush()
+    }
+}
+
+/// Type, minimizer sequence (only for minimizer section) and position of each section written in output
+pub struct Index<W: Write> {
+    output: Output<W>,
+    sections: Vec<(u8, Option<String>, u64)>,
+}
+
+impl<W: Write> Index<W> {
+    pub fn new(output: Output<W>) -> Self {
+        Index {
+            output,
+            sections: Vec::new(),
+        }
+    }
+
+    /// Record position of a section, must be call just before writing it
+    pub fn add(&mut self, section_type: u8, minimizer: Option<String>) {
+        self.sections
+            .push((section_type, minimizer, self.output.position()));
+    }
+
+    /// Variables of minimizer table, name is `minimizer_{minimizer}_{n}` and value absolute position of n-th section of this minimizer
+    pub fn minimizers(&self) -> Vec<(String, u64)> {
+        let mut occurrences = rustc_hash::FxHashMap::default();
+
+        self.sections
+            .iter()
+            .filter_map(|(_, minimizer, position)| minimizer.as_ref().map(|m| (m, *position)))
+            .map(|(minimizer, position)| {
+                let n = occurrences.entry(minimizer).or_insert(0);
+                *n += 1;
+
+                (format!("minimizer_{}_{}", minimizer, *n - 1), position)
+            })
+            .collect()
+    }
+
+    /// Write index section and footer
+    pub fn write(&mut self, nb_kmers: u64) -> Result<()> {
+        let first_index = self.output.position();
+
+        // type and offset of each entry
+        let index_end = first_index + 1 + 8 + self.sections.len() as u64 * (1 + 8) + 8;
+
+        let mut buffer = vec![b'i'];
+        buffer.extend(&(self.sections.len() as u64).to_be_bytes());
+        for (section_type, _, position) in self.sections.iter() {
+            buffer.push(*section_type);
+            buffer.extend(&(*position as i64 - index_end as i64).to_be_bytes());
+        }
+        // no next index section
+        buffer.extend(&0_i64.to_be_bytes());
+
+        let variables: [(&str, u64); 2] = [("first_index", first_index), ("nb_kmers", nb_kmers)];
+        let footer_size = 1
+            + 8
+            + variables
+                .iter()
+                .chain(std::iter::once(&("footer_size", 0)))
+                .map(|(name, _)| name.len() as u64 + 1 + 8)
+                .sum::<u64>();
+
+        buffer.push(b'v');
+        buffer.extend(&(variables.len() as u64 + 1).to_be_bytes());
+        for (name, value) in variables
+            .iter()
+            .chain(std::iter::once(&("footer_size", footer_size)))
+        {
+            buffer.extend(name.as_bytes());
+            buffer.push(0);
+            buffer.extend(&value.to_be_bytes());
+        }
+
+        self.output.write_all(&buffer)?;
+
+        Ok(())
+    }
+
+    /// Flush output shared with kff writer
+    pub fn flush(&mut self) -> Result<()> {
+        self.output.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_position() {
+        let mut output = Output::new(Vec::new());
+        let other = output.clone();
+
+        output.write_all(b"KFF").unwrap();
+        output.write_all(b"v").unwrap();
+
+        assert_eq!(other.position(), 4);
+    }
+
+    #[test]
+    fn index_footer() {
+        let mut output = Output::new(Vec::new());
+        let mut index = Index::new(output.clone());
+
+        output.write_all(b"head").unwrap();
+        index.add(b'm', Some("GTCAG".to_string()));
+        output.write_all(b"minimizer").unwrap();
+        index.add(b'r', None);
+        output.write_all(b"raw").unwrap();
+
+        index.write(42).unwrap();
+        drop(index);
+
+        let inner = Rc::try_unwrap(output.inner).ok().unwrap().into_inner().0;
+
+        let mut truth = b"headminimizerraw".to_vec();
+        // index section start at 16 and end at 16 + 1 + 8 + (1 + 8) * 2 + 8 = 51
+        truth.push(b'i');
+        truth.extend(&2_u64.to_be_bytes());
+        truth.push(b'm');
+        truth.extend(&(4_i64 - 51).to_be_bytes());
+        truth.push(b'r');
+        truth.extend(&(13_i64 - 51).to_be_bytes());
+        truth.extend(&0_i64.to_be_bytes());
+        truth.push(b'v');
+        truth.extend(&3_u64.to_be_bytes());
+        truth.extend(b"first_index\0");
+        truth.extend(&16_u64.to_be_bytes());
+        truth.extend(b"nb_kmers\0");
+        truth.extend(&42_u64.to_be_bytes());
+        truth.extend(b"footer_size\0");
+        truth.extend(&(1_u64 + 8 + 20 + 17 + 20).to_be_bytes());
+
+        assert_eq!(inner, truth);
+    }
+
+    #[test]
+    fn minimizers() {
+        let mut output = Output::new(Vec::new());
+        let mut index = Index::new(output.clone());
+
+        index.add(b'm', Some("ACG".to_string()));
+        output.write_all(b"first").unwrap();
+        index.add(b'm', Some("TTA".to_string()));
+        output.write_all(b"second").unwrap();
+        index.add(b'r', None);
+        output.write_all(b"raw").unwrap();
+        index.add(b'm', Some("ACG".to_string()));
+
+        assert_eq!(
+            index.minimizers(),
+            vec![
+                ("minimizer_ACG_0".to_string(), 0),
+                ("minimizer_TTA_0".to_string(), 5),
+                ("minimizer_ACG_1".to_string(), 14),
+            ]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Open input file, `-` is read as stdin and gzip, bzip2, zstd or xz compression is detect with magic bytes.
+//!
+//! Crc32 of each file opened is computed on raw bytes while it's parsed, so it's available without reading file one more time once the file is read until its end.
+
+/* std use */
+use std::cell::RefCell;
+use std::io::{BufRead, Read};
+use std::rc::Rc;
+
+/* crate use */
+use anyhow::{Context, Result};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+/// Extensions of input formats and compressions, they are removed from input name
+const EXTENSIONS: &[&str] = &[
+    "gz", "bz2", "zst", "xz", "csv", "tsv", "txt", "fa", "fasta", "fna", "fq", "fastq", "gfa",
+    "kff", "jf", "bin",
+];
+
+/// Crc32 and number of bytes read
+type Crc = Rc<RefCell<(flate2::Crc, u64)>>;
+
+thread_local! {
+    /// Crc32 of files opened by path
+    static CRCS: RefCell<rustc_hash::FxHashMap<String, Crc>> = RefCell::new(rustc_hash::FxHashMap::default());
+}
+
+/// Open path, `-` open stdin, compressed file are transparently decompressed
+pub fn open(path: &str) -> Result<Box<dyn BufRead>> {
+    if path == "-" {
+        decompress(std::io::BufReader::new(std::io::stdin()))
+    } else {
+        let crc = Crc::default();
+        CRCS.with(|crcs| crcs.borrow_mut().insert(path.to_string(), crc.clone()));
+
+        decompress(std::io::BufReader::new(CrcReader {
+            inner: std::fs::File::open(path)
+                .with_context(|| format!("Open input file {}", path))?,
+            crc,
+        }))
+    }
+}
+
+/// Crc32 of a file opened with `open`, None if it wasn't read until its end
+pub fn crc32(path: &str) -> Option<u32> {
+    let size = std::fs::metadata(path).ok()?.len();
+
+    CRCS.with(|crcs| {
+        crcs.borrow().get(path).and_then(|crc| {
+            let (crc, length) = &*crc.borrow();
+
+            if *length == size {
+                Some(crc.sum())
+            } else {
+                None
+            }
+        })
+    })
+}
+
+/// Update crc32 with each byte read
+struct CrcReader<R: Read> {
+    inner: R,
+    crc: Crc,
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = self.inner.read(buf)?;
+
+        let mut crc = self.crc.borrow_mut();
+        crc.0.update(&buf[..length]);
+        crc.1 += length as u64;
+
+        Ok(length)
+    }
+}
+
+/// Paths of inputs followed by paths in list file (one by line, empty lines are ignored), directories and glob patterns are expanded
+pub fn paths(inputs: &[String], list: Option<&str>) -> Result<Vec<String>> {
+    let mut paths = inputs.to_vec();
+
+    if let Some(list) = list {
+        for line in open(list)?.lines() {
+            let line = line.with_context(|| format!("Read input list {}", list))?;
+
+            if !line.trim().is_empty() {
+                paths.push(line.trim().to_string());
+            }
+        }
+    }
+
+    let mut expanded = Vec::with_capacity(paths.len());
+    for path in paths {
+        expanded.extend(expand(path)?);
+    }
+
+    Ok(expanded)
+}
+
+/// Files of a directory or matching a glob pattern (in lexicographic order), other paths are keep as is
+fn expand(path: String) -> Result<Vec<String>> {
+    let mut paths = if std::path::Path::new(&path).is_dir() {
+        std::fs::read_dir(&path)
+            .with_context(|| format!("Read input directory {}", path))?
+            .collect::<std::io::Result<Vec<std::fs::DirEntry>>>()
+            .with_context(|| format!("Read input directory {}", path))?
+            .into_iter()
+            .map(|entry| entry.path())
+            .filter(|p| p.is_file())
+            .filter(|p| {
+                !p.file_name()
+                    .map(|x| x.to_string_lossy().starts_with('.'))
+                    .unwrap_or(true)
+            })
+            .map(|p| p.to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+    } else if path.contains(&['*', '?', '['][..]) {
+        glob::glob(&path)
+            .with_context(|| format!("Parse glob pattern {}", path))?
+            .collect::<std::result::Result<Vec<std::path::PathBuf>, _>>()
+            .with_context(|| format!("Expand glob pattern {}", path))?
+            .into_iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+    } else {
+        return Ok(vec![path]);
+    };
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Name of input, file name without known extensions of formats and compressions
+pub fn name(path: &str) -> String {
+    let mut name = std::path::Path::new(path)
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    while let Some(position) = name.rfind('.') {
+        if position == 0 || !EXTENSIONS.contains(&name[position + 1..].to_lowercase().as_str()) {
+            break;
+        }
+
+        name.truncate(position);
+    }
+
+    name
+}
+
+/// Check first bytes of reader and add the corresponding decompression layer
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> Result<Box<dyn BufRead>> {
+    let magic = reader
+        .fill_buf()
+        .with_context(|| "Read input magic bytes")?;
+
+    if magic.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(std::io::BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        )))
+    } else if magic.starts_with(BZIP2_MAGIC) {
+        Ok(Box::new(std::io::BufReader::new(
+            bzip2::bufread::BzDecoder::new(reader),
+        )))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(std::io::BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(reader)
+                .with_context(|| "Init zstd decoder")?,
+        )))
+    } else if magic.starts_with(XZ_MAGIC) {
+        Ok(Box::new(std::io::BufReader::new(
+            xz2::bufread::XzDecoder::new(reader),
+        )))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::{Read, Write};
+
+    const CONTENT: &[u8] = b"AAAAATCAACT,1\nAAAACGATTGC,1\n";
+
+    fn read_all(compressed: Vec<u8>) -> Vec<u8> {
+        let mut res = Vec::new();
+
+        decompress(std::io::Cursor::new(compressed))
+            .unwrap()
+            .read_to_end(&mut res)
+            .unwrap();
+
+        res
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(read_all(CONTENT.to_vec()), CONTENT);
+    }
+
+    #[test]
+    fn gzip() {
+        let mut writer = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        writer.write_all(CONTENT).unwrap();
+
+        assert_eq!(read_all(writer.finish().unwrap()), CONTENT);
+    }
+
+    #[test]
+    fn bzip2() {
+        let mut writer = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        writer.write_all(CONTENT).unwrap();
+
+        assert_eq!(read_all(writer.finish().unwrap()), CONTENT);
+    }
+
+    #[test]
+    fn zstd() {
+        assert_eq!(read_all(zstd::encode_all(CONTENT, 0).unwrap()), CONTENT);
+    }
+
+    #[test]
+    fn xz() {
+        let mut writer = xz2::write::XzEncoder::new(Vec::new(), 6);
+        writer.write_all(CONTENT).unwrap();
+
+        assert_eq!(read_all(writer.finish().unwrap()), CONTENT);
+    }
+
+    #[test]
+    fn crc32_() {
+        let path = std::env::temp_dir().join("kmers2kff_crc32.txt");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, b"123456789").unwrap();
+
+        assert_eq!(crc32(path), None);
+
+        let mut content = Vec::new();
+        open(path).unwrap().read_to_end(&mut content).unwrap();
+
+        // crc32 check value of 123456789
+        assert_eq!(crc32(path), Some(0xcbf4_3926));
+
+        // file not read until its end
+        std::fs::write(path, vec![b'A'; 1 << 20]).unwrap();
+        open(path).unwrap().read_exact(&mut [0; 10]).unwrap();
+        assert_eq!(crc32(path), None);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn expand_paths() {
+        let data = vec![
+            "tests/data/large_count.csv".to_string(),
+            "tests/data/matrix.csv".to_string(),
+            "tests/data/sorted.csv".to_string(),
+            "tests/data/sorted_matrix.csv".to_string(),
+            "tests/data/test.csv".to_string(),
+            "tests/data/test.csv.gz".to_string(),
+        ];
+
+        assert_eq!(paths(&["tests/data".to_string()], None).unwrap(), data);
+        assert_eq!(
+            paths(&["tests/data/*.csv".to_string()], None).unwrap(),
+            data[..5].to_vec()
+        );
+        assert_eq!(
+            paths(&["-".to_string()], None).unwrap(),
+            vec!["-".to_string()]
+        );
+    }
+
+    #[test]
+    fn name_() {
+        assert_eq!(name("tests/data/test.csv.gz"), "test");
+        assert_eq!(name("sample"), "sample");
+        assert_eq!(name("S1.R1.csv"), "S1.R1");
+        assert_eq!(name("reads.FASTQ.GZ"), "reads");
+        assert_eq!(name("db.kmc_pre"), "db.kmc_pre");
+        assert_eq!(name(".csv"), ".csv");
+    }
+}
@@ -0,0 +1,261 @@
+//! Read a Jellyfish database in binary format (`jellyfish count` or `jellyfish dump` output).
+//!
+//! File begin with the size of the header on 9 ASCII digits, followed by the header in JSON. Binary records start at header field `offset` (or just after header), each record is the kmer (2 bits by nucleotide, first nucleotide in most significant bits, little endian) followed by the count (little endian) on `counter_len` bytes (`val_len` is the bit width of hash values, it's only used if `counter_len` is absent).
+//!
+//! Jellyfish encode nucleotide A=00, C=01, G=10, T=11, kmer are convert in [seq2bits](crate::seq2bits) encoding.
+
+/* std use */
+use std::io::Read;
+
+/* crate use */
+use anyhow::{Context, Result};
+
+/* local use */
+use crate::error::Error;
+use crate::input;
+use crate::seq2bits;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub format: String,
+    pub key_len: u64,
+    pub counter_len: u64,
+    pub canonical: bool,
+}
+
+impl Header {
+    /// Kmer size
+    pub fn k(&self) -> u64 {
+        self.key_len / 2
+    }
+
+    /// Size in bytes of kmer in record
+    pub fn key_bytes(&self) -> usize {
+        ((self.key_len - 1) / 8 + 1) as usize
+    }
+
+    /// Kmer length must be even and fit in 128 bits, count must fit in 64 bits
+    fn is_valid(&self) -> bool {
+        self.key_len & 1 == 0
+            && (2..=128).contains(&self.key_len)
+            && (1..=8).contains(&self.counter_len)
+    }
+}
+
+pub struct Reader<R: Read> {
+    header: Header,
+    input: R,
+    record: Vec<u8>,
+    record_number: u64,
+    encoding: seq2bits::Encoding,
+}
+
+impl Reader<Box<dyn std::io::BufRead>> {
+    pub fn from_path(path: &str, encoding: seq2bits::Encoding) -> Result<Self> {
+        Self::new(input::open(path)?, encoding)
+    }
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(mut input: R, encoding: seq2bits::Encoding) -> Result<Self> {
+        let mut length = [0; 9];
+        input
+            .read_exact(&mut length)
+            .with_context(|| "Read Jellyfish header length")?;
+        let length = std::str::from_utf8(&length)
+            .ok()
+            .and_then(|x| x.trim().parse::<u64>().ok())
+            .ok_or(Error::JellyfishBadHeader)?;
+
+        let mut json = Vec::new();
+        (&mut input)
+            .take(length)
+            .read_to_end(&mut json)
+            .with_context(|| "Read Jellyfish header")?;
+        if json.len() as u64 != length {
+            return Err(Error::JellyfishBadHeader.into());
+        }
+        let end = json.iter().rposition(|x| *x == b'}').unwrap_or(0) + 1;
+        let json: serde_json::Value =
+            serde_json::from_slice(&json[..end]).map_err(|_| Error::JellyfishBadHeader)?;
+
+        let header = Header {
+            format: json["format"].as_str().unwrap_or("").to_string(),
+            key_len: json["key_len"].as_u64().ok_or(Error::JellyfishBadHeader)?,
+            counter_len: json["counter_len"]
+                .as_u64()
+                .or_else(|| json["val_len"].as_u64())
+                .ok_or(Error::JellyfishBadHeader)?,
+            canonical: json["canonical"].as_bool().unwrap_or(false),
+        };
+
+        if header.format != "binary/sorted" {
+            return Err(Error::JellyfishUnsupportedFormat(header.format).into());
+        }
+        if !header.is_valid() {
+            return Err(Error::JellyfishBadHeader.into());
+        }
+
+        // records could begin after a padding
+        if let Some(offset) = json["offset"].as_u64() {
+            let consumed = 9 + length;
+            if offset > consumed {
+                std::io::copy(
+                    &mut (&mut input).take(offset - consumed),
+                    &mut std::io::sink(),
+                )
+                .with_context(|| "Skip Jellyfish header padding")?;
+            }
+        }
+
+        Ok(Self {
+            record: vec![0; header.key_bytes() + header.counter_len as usize],
+            header,
+            input,
+            record_number: 0,
+            encoding,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Read next record, return None at end of file
+    fn next_kmer(&mut self) -> Result<Option<(u128, u64)>> {
+        let mut readed = 0;
+        while readed < self.record.len() {
+            match self.input.read(&mut self.record[readed..]) {
+                Ok(0) if readed == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(Error::JellyfishTruncatedRecord(self.record_number).into());
+                }
+                Ok(n) => readed += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).with_context(|| "Read Jellyfish record"),
+            }
+        }
+        self.record_number += 1;
+
+        let key_bytes = self.header.key_bytes();
+
+        let mut kmer = 0;
+        for (i, byte) in self.record[..key_bytes].iter().enumerate() {
+            kmer |= (*byte as u128) << (8 * i);
+        }
+
+        let mut count = 0;
+        for (i, byte) in self.record[key_bytes..].iter().enumerate() {
+            count |= (*byte as u64) << (8 * i);
+        }
+
+        Ok(Some((
+            self.encoding.acgt2bit(kmer, self.header.k() as u8),
+            count,
+        )))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<(u128, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_kmer().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTG: seq2bits::Encoding = seq2bits::Encoding::ACTG;
+
+    /// Database with header fields as Jellyfish writes them, val_len is the bit width of hash values
+    fn database(format: &str) -> Vec<u8> {
+        with_header(&format!(
+            "\"format\":\"{}\",\"key_len\":10,\"val_len\":7,\"counter_len\":2,\"canonical\":true",
+            format
+        ))
+    }
+
+    fn with_header(fields: &str) -> Vec<u8> {
+        let json = format!("{{{},\"offset\":128}}", fields);
+
+        let mut data = format!("{:09}", json.len() + 3).into_bytes();
+        data.extend(json.as_bytes());
+        data.extend(&[0; 3]);
+        data.resize(128, 0);
+
+        // ACGTA -> 0001101100 in ACGT order
+        data.extend(&0b0001101100u16.to_le_bytes());
+        data.extend(&3u16.to_le_bytes());
+        // TGGGG -> 1110101010 in ACGT order
+        data.extend(&0b1110101010u16.to_le_bytes());
+        data.extend(&300u16.to_le_bytes());
+
+        data
+    }
+
+    #[test]
+    fn header() {
+        let reader = Reader::new(std::io::Cursor::new(database("binary/sorted")), ACTG).unwrap();
+
+        assert_eq!(
+            reader.header(),
+            &Header {
+                format: "binary/sorted".to_string(),
+                key_len: 10,
+                counter_len: 2,
+                canonical: true,
+            }
+        );
+        assert_eq!(reader.header().k(), 5);
+    }
+
+    #[test]
+    fn kmers() {
+        let mut reader =
+            Reader::new(std::io::Cursor::new(database("binary/sorted")), ACTG).unwrap();
+
+        assert_eq!(reader.next().unwrap().unwrap(), (ACTG.seq2bit(b"ACGTA"), 3));
+        assert_eq!(
+            reader.next_kmer().unwrap().unwrap(),
+            (ACTG.seq2bit(b"TGGGG"), 300)
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn bad_format() {
+        assert!(Reader::new(std::io::Cursor::new(database("bloomcounter")), ACTG).is_err());
+    }
+
+    #[test]
+    fn bad_header() {
+        for fields in &[
+            "\"format\":\"binary/sorted\",\"key_len\":0,\"counter_len\":2",
+            "\"format\":\"binary/sorted\",\"key_len\":9,\"counter_len\":2",
+            "\"format\":\"binary/sorted\",\"key_len\":130,\"counter_len\":2",
+            "\"format\":\"binary/sorted\",\"key_len\":10,\"counter_len\":0",
+            "\"format\":\"binary/sorted\",\"key_len\":10,\"counter_len\":9",
+            "\"format\":\"binary/sorted\",\"key_len\":10,\"counter_len\":18446744073709551615",
+        ] {
+            assert!(Reader::new(std::io::Cursor::new(with_header(fields)), ACTG).is_err());
+        }
+
+        // header length is upper than data
+        let mut data = b"000001000{}".to_vec();
+        data.extend(&[0; 10]);
+        assert!(Reader::new(std::io::Cursor::new(data), ACTG).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut data = database("binary/sorted");
+        data.pop();
+
+        let reader = Reader::new(std::io::Cursor::new(data), ACTG).unwrap();
+
+        assert!(reader.collect::<Result<Vec<_>>>().is_err());
+    }
+}
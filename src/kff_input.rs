@@ -0,0 +1,163 @@
+//! Read kmers of an existing KFF file to re-encode it.
+//!
+//! Data of each kmer is decoded in counts with count width (`count_width=` entry of header metadata, 1 if it's absent), sample names are read in `sample=` entries of header metadata.
+
+/* std use */
+use std::cell::Cell;
+use std::io::Read;
+use std::rc::Rc;
+
+/* crate use */
+use anyhow::{Context, Result};
+use kff::seq2bits::Bits2Nuc;
+
+/* local use */
+use crate::bucket;
+use crate::cli;
+use crate::error::Error;
+use crate::input;
+use crate::seq2bits;
+
+/// Kmers of a KFF file
+pub struct Kmers {
+    /// Kmer size, None if file contains no kmer
+    pub k: Option<u8>,
+    /// Sample names read in header metadata, empty if they are absent
+    pub samples: Vec<String>,
+    /// Number of counts associate to each kmer
+    pub nb_samples: usize,
+    pub kmers: Vec<(u128, bucket::Counts)>,
+}
+
+/// Input shared with kff reader, record if end of file is reach and number of bytes read since last section
+struct Input<R: Read> {
+    inner: R,
+    state: Rc<Cell<(bool, u64)>>,
+}
+
+impl<R: Read> Read for Input<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = self.inner.read(buf)?;
+
+        let (_, read) = self.state.get();
+        self.state
+            .set((length == 0 && !buf.is_empty(), read + length as u64));
+
+        Ok(length)
+    }
+}
+
+/// Read all kmers and data of a KFF file, `-` read stdin, compressed file are transparently decompressed
+pub fn read(path: &str, encoding: seq2bits::Encoding) -> Result<Kmers> {
+    let state = Rc::new(Cell::new((false, 0)));
+    let mut input = Input {
+        inner: input::open(path)?,
+        state: state.clone(),
+    };
+
+    let mut reader = kff::Reader::new(&mut input).with_context(|| "Read KFF header")?;
+    let rev_encoding = reader.rev_encoding();
+
+    let (width, samples) = parse_metadata(reader.metadata())?;
+
+    let mut k = None;
+    let mut nb_samples = 0;
+    let mut kmers = Vec::new();
+
+    loop {
+        state.set((state.get().0, 0));
+        let section = match reader.next_section() {
+            Ok(section) => section,
+            // end of file is reach before any byte of a new section
+            Err(_) if state.get() == (true, 0) => break,
+            Err(e) => return Err(e).with_context(|| "Read KFF section"),
+        };
+
+        for result in section {
+            let kmer = result.with_context(|| "Read KFF kmer")?;
+            let seq = kmer.seq().into_nuc(rev_encoding);
+
+            if k.is_none() {
+                k = Some(cli::kmer_size(seq.len() as u64)?);
+            }
+            nb_samples = kmer.data().len() / width;
+
+            kmers.push((encoding.seq2bit(&seq), decode(kmer.data(), width)?));
+        }
+    }
+
+    log::info!("{} kmers read in KFF file", kmers.len());
+
+    Ok(Kmers {
+        k,
+        samples,
+        nb_samples,
+        kmers,
+    })
+}
+
+/// Read count width and sample names in header metadata
+fn parse_metadata(metadata: &[u8]) -> Result<(usize, Vec<String>)> {
+    let mut width = 1;
+    let mut samples = Vec::new();
+
+    for line in String::from_utf8_lossy(metadata).lines() {
+        if let Some(value) = line.strip_prefix("count_width=") {
+            width = value
+                .parse()
+                .ok()
+                .filter(|w| [1, 2, 4, 8].contains(w))
+                .ok_or_else(|| Error::KffCountWidth(value.to_string()))?;
+        } else if let Some(name) = line.strip_prefix("sample=") {
+            samples.push(name.to_string());
+        }
+    }
+
+    Ok((width, samples))
+}
+
+/// Decode data of a kmer in counts of width bytes (little endian)
+fn decode(data: &[u8], width: usize) -> Result<bucket::Counts> {
+    let chunks = data.chunks_exact(width);
+    if !chunks.remainder().is_empty() {
+        return Err(Error::KffDataSize {
+            data_size: data.len(),
+            width,
+        }
+        .into());
+    }
+
+    Ok(chunks
+        .map(|chunk| {
+            chunk
+                .iter()
+                .rev()
+                .fold(0, |count, byte| (count << 8) | *byte as u64)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn metadata() {
+        assert_eq!(parse_metadata(b"").unwrap(), (1, Vec::new()));
+        assert_eq!(
+            parse_metadata(b"k=11\ncount_width=4\nsample=a\nsample=b\n").unwrap(),
+            (4, vec!["a".to_string(), "b".to_string()])
+        );
+        assert!(parse_metadata(b"count_width=3\n").is_err());
+    }
+
+    #[test]
+    fn decode_() {
+        let mut data = Vec::new();
+        bucket::encode(&[3, 300, 70000], 4, &mut data);
+
+        assert_eq!(decode(&data, 4).unwrap(), vec![3, 300, 70000]);
+        assert_eq!(decode(&[1, 2], 1).unwrap(), vec![1, 2]);
+        assert!(decode(&[1, 2, 3], 2).is_err());
+    }
+}
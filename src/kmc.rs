@@ -0,0 +1,337 @@
+//! Read a KMC database (`.kmc_pre` and `.kmc_suf` files) without dump it in csv.
+//!
+//! Prefix file contains a marker `KMCP`, the lookup table of prefix (one by bin for KMC 2 and upper), the signature map (only KMC 2 and upper), the header, KMC version, header size and a marker `KMCP`.
+//! Suffix file contains a marker `KMCS`, for each kmer the suffix (4 nucleotides by bytes) followed by the counter (little endian) and a marker `KMCS`.
+//!
+//! KMC encode nucleotide A=00, C=01, G=10, T=11, kmer are convert in [seq2bits](crate::seq2bits) encoding.
+
+/* std use */
+use std::io::Read;
+
+/* crate use */
+use anyhow::{Context, Result};
+
+/* local use */
+use crate::error::Error;
+use crate::seq2bits;
+
+const PREFIX_MARKER: &[u8] = b"KMCP";
+const SUFFIX_MARKER: &[u8] = b"KMCS";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub version: u32,
+    pub k: u32,
+    pub mode: u32,
+    pub counter_size: u32,
+    pub lut_prefix_length: u32,
+    pub signature_len: u32,
+    pub min_count: u32,
+    pub max_count: u32,
+    pub total_kmers: u64,
+}
+
+impl Header {
+    /// Number of nucleotide store in suffix file
+    pub fn suffix_length(&self) -> u32 {
+        self.k - self.lut_prefix_length
+    }
+
+    /// Size in bytes of a record in suffix file
+    pub fn record_size(&self) -> usize {
+        (self.suffix_length() / 4 + self.counter_size) as usize
+    }
+}
+
+pub struct Reader<R: Read> {
+    header: Header,
+    lut: Vec<u64>,
+    suffix: R,
+    prefix_mask: usize,
+    prefix_index: usize,
+    suffix_number: u64,
+    record: Vec<u8>,
+    encoding: seq2bits::Encoding,
+}
+
+impl Reader<std::io::BufReader<std::fs::File>> {
+    /// Open a KMC database, path could be the database name or one of the two files
+    pub fn from_path(path: &str, encoding: seq2bits::Encoding) -> Result<Self> {
+        let database = path
+            .trim_end_matches(".kmc_pre")
+            .trim_end_matches(".kmc_suf");
+
+        let prefix = std::fs::read(format!("{}.kmc_pre", database))
+            .with_context(|| format!("Read KMC prefix file {}.kmc_pre", database))?;
+        let suffix = std::io::BufReader::new(
+            std::fs::File::open(format!("{}.kmc_suf", database))
+                .with_context(|| format!("Open KMC suffix file {}.kmc_suf", database))?,
+        );
+
+        Self::new(&prefix, suffix, encoding)
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Build a reader from content of prefix file and suffix file reader
+    pub fn new(prefix: &[u8], mut suffix: R, encoding: seq2bits::Encoding) -> Result<Self> {
+        let (header, lut) = parse_prefix(prefix)?;
+
+        if header.mode != 0 {
+            return Err(Error::KmcQualityMode.into());
+        }
+
+        let mut marker = [0; 4];
+        suffix
+            .read_exact(&mut marker)
+            .with_context(|| "Read KMC suffix marker")?;
+        if marker != SUFFIX_MARKER {
+            return Err(Error::KmcBadMarker.into());
+        }
+
+        Ok(Self {
+            prefix_mask: (1 << (2 * header.lut_prefix_length)) - 1,
+            record: vec![0; header.record_size()],
+            header,
+            lut,
+            suffix,
+            prefix_index: 0,
+            suffix_number: 0,
+            encoding,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn next_kmer(&mut self) -> Result<(u128, u32)> {
+        while self.prefix_index + 1 < self.lut.len()
+            && self.lut[self.prefix_index + 1] <= self.suffix_number
+        {
+            self.prefix_index += 1;
+        }
+
+        self.suffix
+            .read_exact(&mut self.record)
+            .with_context(|| format!("Read KMC suffix record {}", self.suffix_number))?;
+        self.suffix_number += 1;
+
+        let suffix_size = (self.header.suffix_length() / 4) as usize;
+
+        let mut kmer = (self.prefix_index & self.prefix_mask) as u128;
+        for byte in &self.record[..suffix_size] {
+            kmer = (kmer << 8) | *byte as u128;
+        }
+
+        let mut count = 0;
+        for (i, byte) in self.record[suffix_size..].iter().enumerate() {
+            count |= (*byte as u32) << (8 * i);
+        }
+
+        Ok((self.encoding.acgt2bit(kmer, self.header.k as u8), count))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<(u128, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.suffix_number == self.header.total_kmers {
+            return None;
+        }
+
+        Some(self.next_kmer().map(|(kmer, count)| (kmer, count as u64)))
+    }
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> Result<u32> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(buffer.get(offset..offset + 4).ok_or(Error::KmcBadHeader)?);
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(buffer: &[u8], offset: usize) -> Result<u64> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(buffer.get(offset..offset + 8).ok_or(Error::KmcBadHeader)?);
+
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Parse header and lookup table of prefix file
+pub fn parse_prefix(prefix: &[u8]) -> Result<(Header, Vec<u64>)> {
+    let size = prefix.len();
+
+    if size < 16 || &prefix[..4] != PREFIX_MARKER || &prefix[size - 4..] != PREFIX_MARKER {
+        return Err(Error::KmcBadMarker.into());
+    }
+
+    let header_offset = read_u32(prefix, size - 8)? as usize;
+    let version = read_u32(prefix, size - 12)?;
+    let header_pos = size
+        .checked_sub(header_offset + 8)
+        .ok_or(Error::KmcBadMarker)?;
+
+    let header = match version {
+        0 => Header {
+            version,
+            k: read_u32(prefix, header_pos)?,
+            mode: read_u32(prefix, header_pos + 4)?,
+            counter_size: read_u32(prefix, header_pos + 8)?,
+            lut_prefix_length: read_u32(prefix, header_pos + 12)?,
+            signature_len: 0,
+            min_count: read_u32(prefix, header_pos + 16)?,
+            max_count: read_u32(prefix, header_pos + 20)?,
+            total_kmers: read_u64(prefix, header_pos + 24)?,
+        },
+        0x200 => Header {
+            version,
+            k: read_u32(prefix, header_pos)?,
+            mode: read_u32(prefix, header_pos + 4)?,
+            counter_size: read_u32(prefix, header_pos + 8)?,
+            lut_prefix_length: read_u32(prefix, header_pos + 12)?,
+            signature_len: read_u32(prefix, header_pos + 16)?,
+            min_count: read_u32(prefix, header_pos + 20)?,
+            max_count: read_u32(prefix, header_pos + 24)?,
+            total_kmers: read_u64(prefix, header_pos + 28)?,
+        },
+        _ => return Err(Error::KmcUnsupportedVersion(version).into()),
+    };
+
+    // header fields are used as shift or subtraction, check them before
+    if header.lut_prefix_length > header.k
+        || header.lut_prefix_length >= 16
+        || header.signature_len >= 16
+        || header.counter_size > 4
+    {
+        return Err(Error::KmcBadHeader.into());
+    }
+
+    let signature_map_size = if version == 0x200 {
+        ((1 << (2 * header.signature_len)) + 1) * 4
+    } else {
+        0
+    };
+    let lut_end = header_pos
+        .checked_sub(signature_map_size)
+        .ok_or(Error::KmcBadMarker)?;
+
+    // lookup table is followed by a guard, we keep only complete tables
+    let single_lut_size = 1 << (2 * header.lut_prefix_length);
+    let nb_entries = (lut_end.saturating_sub(4) / 8) / single_lut_size * single_lut_size;
+
+    let mut lut = Vec::with_capacity(nb_entries + 1);
+    for i in 0..nb_entries {
+        lut.push(read_u64(prefix, 4 + i * 8)?);
+    }
+    lut.push(header.total_kmers + 1);
+
+    Ok((header, lut))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTG: seq2bits::Encoding = seq2bits::Encoding::ACTG;
+
+    fn database() -> (Vec<u8>, Vec<u8>) {
+        // k = 5, prefix of 1 nucleotide, suffix of 4 nucleotides, counter of 2 bytes
+        // kmers: ACGTA 3, CAAAA 300, CTTTT 1, TGGGG 42 in KMC encoding
+        let mut prefix = PREFIX_MARKER.to_vec();
+
+        // signature of 1 nucleotide, 2 bins
+        for lut in &[[0, 1, 3, 3], [4, 4, 4, 4]] {
+            for value in lut.iter() {
+                prefix.extend(&(*value as u64).to_le_bytes());
+            }
+        }
+        prefix.extend(&4u64.to_le_bytes());
+        for _ in 0..5 {
+            prefix.extend(&0u32.to_le_bytes());
+        }
+
+        let header_begin = prefix.len();
+        for value in &[5u32, 0, 2, 1, 1, 1, 1000] {
+            prefix.extend(&value.to_le_bytes());
+        }
+        prefix.extend(&4u64.to_le_bytes());
+        prefix.push(0);
+        prefix.extend(&[0; 27]);
+        prefix.extend(&0x200u32.to_le_bytes());
+        let header_offset = (prefix.len() - header_begin) as u32;
+        prefix.extend(&header_offset.to_le_bytes());
+        prefix.extend(PREFIX_MARKER);
+
+        let mut suffix = SUFFIX_MARKER.to_vec();
+        suffix.extend(&[0b0110_1100, 3, 0]); // A CGTA
+        suffix.extend(&[0b0000_0000, 44, 1]); // C AAAA
+        suffix.extend(&[0b1111_1111, 1, 0]); // C TTTT
+        suffix.extend(&[0b1010_1010, 42, 0]); // T GGGG
+        suffix.extend(SUFFIX_MARKER);
+
+        (prefix, suffix)
+    }
+
+    #[test]
+    fn header() {
+        let (prefix, _) = database();
+
+        let (header, lut) = parse_prefix(&prefix).unwrap();
+
+        assert_eq!(
+            header,
+            Header {
+                version: 0x200,
+                k: 5,
+                mode: 0,
+                counter_size: 2,
+                lut_prefix_length: 1,
+                signature_len: 1,
+                min_count: 1,
+                max_count: 1000,
+                total_kmers: 4,
+            }
+        );
+        assert_eq!(lut, vec![0, 1, 3, 3, 4, 4, 4, 4, 5]);
+    }
+
+    #[test]
+    fn corrupted_header() {
+        let (prefix, _) = database();
+
+        // truncated prefix file
+        let mut truncated = prefix[..8].to_vec();
+        truncated.extend(&prefix[prefix.len() - 12..]);
+        assert!(parse_prefix(&truncated).is_err());
+
+        // header offset too small, header is read after end of file
+        let mut offset = prefix.clone();
+        let size = offset.len();
+        offset[size - 8..size - 4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(parse_prefix(&offset).is_err());
+
+        // lut prefix length upper than k
+        let header_begin = size - 8 - read_u32(&prefix, size - 8).unwrap() as usize;
+        let mut lut = prefix.clone();
+        lut[header_begin + 12..header_begin + 16].copy_from_slice(&40u32.to_le_bytes());
+        assert!(parse_prefix(&lut).is_err());
+    }
+
+    #[test]
+    fn kmers() {
+        let (prefix, suffix) = database();
+
+        let mut reader = Reader::new(&prefix, std::io::Cursor::new(suffix), ACTG).unwrap();
+
+        assert_eq!(reader.next_kmer().unwrap(), (ACTG.seq2bit(b"ACGTA"), 3));
+        assert_eq!(reader.next_kmer().unwrap(), (ACTG.seq2bit(b"CAAAA"), 300));
+        assert_eq!(reader.next().unwrap().unwrap(), (ACTG.seq2bit(b"CTTTT"), 1));
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            (ACTG.seq2bit(b"TGGGG"), 42)
+        );
+        assert!(reader.next().is_none());
+    }
+}
@@ -1,154 +1,762 @@
+/* std use */
+use std::io::Write;
+
 /* crate use */
 use anyhow::{anyhow, Context, Result};
 use clap::Clap;
 
 /* mod declaration */
+mod binary;
 mod bucket;
 mod cli;
+mod count;
 mod error;
+mod fastx;
+mod gfa;
+mod index;
+mod input;
+mod jellyfish;
+mod kff_input;
+mod kmc;
+mod metadata;
 mod seq2bits;
+mod shard;
+mod unitig;
 
 fn main() -> Result<()> {
     env_logger::init();
 
     let params = cli::check_params(cli::Command::parse()).with_context(|| "Check parameter")?;
 
+    let inputs = input::paths(&params.input, params.input_list.as_deref())?;
+    if inputs.is_empty() {
+        return Err(error::Error::CliNoInput.into());
+    }
+
+    if params.batch {
+        batch(&params, &inputs)
+    } else {
+        convert(&params, &inputs, &params.output).map(|_| ())
+    }
+}
+
+/// Number of kmers and minimizer buckets written in a kff file
+#[derive(Default)]
+struct Summary {
+    kmers: usize,
+    buckets: usize,
+}
+
+/// Convert each input in its own kff file and print a summary table
+fn batch(params: &cli::Command, inputs: &[String]) -> Result<()> {
+    if !params.output.contains("{name}") {
+        return Err(error::Error::CliBatchOutputTemplate.into());
+    }
+
+    let outputs = inputs
+        .iter()
+        .map(|path| params.output.replace("{name}", &input::name(path)))
+        .collect::<Vec<String>>();
+
+    // an input must not overwrite output of a previous input
+    let mut seens = rustc_hash::FxHashMap::default();
+    for (path, output) in inputs.iter().zip(outputs.iter()) {
+        if let Some(first) = seens.insert(output, path) {
+            return Err(error::Error::BatchSameOutput {
+                first: first.to_string(),
+                second: path.to_string(),
+                output: output.to_string(),
+            }
+            .into());
+        }
+    }
+
+    println!("input\toutput\tkmers\tbuckets\tsize");
+    for (path, output) in inputs.iter().zip(outputs) {
+        log::info!("Convert {} in {}", path, output);
+
+        let summaries = convert(params, std::slice::from_ref(path), &output)
+            .with_context(|| format!("Convert input {}", path))?;
+
+        let paths = if params.shards > 1 {
+            shard::paths(&output, params.shards)?
+        } else {
+            vec![output.clone()]
+        };
+        let mut size = 0;
+        for path in paths.iter() {
+            size += std::fs::metadata(path)
+                .with_context(|| format!("Read size of {}", path))?
+                .len();
+        }
+
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            path,
+            output,
+            summaries.iter().map(|summary| summary.kmers).sum::<usize>(),
+            summaries
+                .iter()
+                .map(|summary| summary.buckets)
+                .sum::<usize>(),
+            size
+        );
+    }
+
+    Ok(())
+}
+
+/// Convert inputs in kff file output, `-` write on stdout, with many shards write a kff file by shard and a manifest
+fn convert(params: &cli::Command, inputs: &[String], output: &str) -> Result<Vec<Summary>> {
+    if params.shards > 1 {
+        let paths = shard::paths(output, params.shards)?;
+
+        let mut outputs = Vec::new();
+        for path in paths.iter() {
+            outputs.push(std::io::BufWriter::new(
+                std::fs::File::create(path).with_context(|| format!("Create output {}", path))?,
+            ));
+        }
+
+        let summaries = write_kff(params, inputs, outputs)?;
+        write_manifest(&shard::manifest_path(output), &paths, &summaries)?;
+
+        Ok(summaries)
+    } else if output == "-" {
+        write_kff(
+            params,
+            inputs,
+            vec![std::io::BufWriter::new(std::io::stdout())],
+        )
+    } else {
+        write_kff(
+            params,
+            inputs,
+            vec![std::io::BufWriter::new(
+                std::fs::File::create(output)
+                    .with_context(|| format!("Create output {}", output))?,
+            )],
+        )
+    }
+}
+
+/// Convert inputs in kff and write it in outputs, one output by shard
+fn write_kff<W: std::io::Write>(
+    params: &cli::Command,
+    inputs: &[String],
+    outputs: Vec<W>,
+) -> Result<Vec<Summary>> {
+    if params.streaming {
+        streaming(params, inputs, outputs)
+    } else {
+        in_memory(params, inputs, outputs)
+    }
+}
+
+/// Write minimizer hash range, number of kmers and buckets of each shard
+fn write_manifest(path: &str, outputs: &[String], summaries: &[Summary]) -> Result<()> {
+    let mut manifest = std::io::BufWriter::new(
+        std::fs::File::create(path).with_context(|| format!("Create manifest {}", path))?,
+    );
+
+    writeln!(
+        manifest,
+        "shard\toutput\tfirst_hash\tlast_hash\tkmers\tbuckets"
+    )?;
+    for (i, (output, summary)) in outputs.iter().zip(summaries).enumerate() {
+        let (first, last) = shard::range(i, outputs.len());
+
+        writeln!(
+            manifest,
+            "{}\t{}\t{:032x}\t{:032x}\t{}\t{}",
+            i, output, first, last, summary.kmers, summary.buckets
+        )?;
+    }
+
+    Ok(())
+}
+
+/// All kmers are store in memory before writing
+fn in_memory<W: std::io::Write>(
+    params: &cli::Command,
+    inputs: &[String],
+    outputs: Vec<W>,
+) -> Result<Vec<Summary>> {
     log::info!("Start of bucket creation");
 
-    // generate bucket
-    let (bob, mini2kmers) =
-        bucket::build(&params.input, params.k, params.m, params.delimiter as u8)?;
+    let mut validator = bucket::Validator::new(
+        params.k,
+        params.invalid_nucleotide,
+        params.lenient,
+        params.encoding,
+    );
+    let mut merger = bucket::Merger::new(params.duplicate, params.presence_only, params.encoding);
+
+    // generate bucket of each input and merge them
+    let mut k = params.k;
+    let mut samples = Vec::new();
+    let mut bob = rustc_hash::FxHashSet::default();
+    let mut mini2kmers = bucket::Buckets::default();
+    for path in inputs.iter() {
+        log::info!("Read input {}", path);
+
+        let (input_k, names, kmers) = open_input(path, k, params, &mut validator)?;
+        let (input_bob, input_mini2kmers) = bucket::build(kmers, input_k, params.m, &mut merger)?;
+
+        if params.presence_only {
+            bucket::merge(&mut mini2kmers, input_mini2kmers, params.merge, 0, 0)
+        } else {
+            bucket::merge(
+                &mut mini2kmers,
+                input_mini2kmers,
+                params.merge,
+                samples.len(),
+                names.len(),
+            )
+        }
+        .with_context(|| format!("Merge input {}", path))?;
+        bob.extend(input_bob);
+
+        if samples.is_empty() || params.merge == cli::Merge::Samples {
+            samples.extend(names);
+        }
+
+        k = Some(input_k);
+        validator.k = k;
+    }
+    let k = k.ok_or(error::Error::CantInferK)?;
+
+    if params.presence_only {
+        samples.clear();
+    }
 
     log::info!("End of bucket creation");
+    validator.log_summary();
+    merger.log_summary();
 
-    // create kff
-    let mut writer = kff::Writer::new(std::fs::File::create(params.output)?, 0b00011011, b"")?;
+    let width = count_width(params, bucket::max_count(mini2kmers.values()))?;
+
+    let mut writers = create_writers(params, outputs, inputs, k, &samples, width)?;
+    let mut summaries = Vec::new();
+    summaries.resize_with(writers.len(), Summary::default);
+
+    write_buckets(
+        &mut writers,
+        &mut summaries,
+        bob,
+        &mini2kmers,
+        k,
+        params,
+        width,
+    )?;
+
+    finish(params, writers, &summaries)?;
+
+    Ok(summaries)
+}
 
-    writer.variables().insert("k".to_string(), params.k as u64);
+/// Kmers of inputs sorted in lexicographic order are read by window of buffer size kmers, each window is bucketed and written before reading the next one
+///
+/// Memory usage is bounded by buffer size kmers plus one kmer by input, a minimizer could have a section in each window.
+fn streaming<W: std::io::Write>(
+    params: &cli::Command,
+    inputs: &[String],
+    outputs: Vec<W>,
+) -> Result<Vec<Summary>> {
+    // count width is written in header before any kmer is read
+    if params.overflow == cli::Overflow::AutoWiden {
+        return Err(error::Error::CliStreamingAutoWiden.into());
+    }
+
+    let mut validators = inputs
+        .iter()
+        .map(|_| {
+            bucket::Validator::new(
+                params.k,
+                params.invalid_nucleotide,
+                params.lenient,
+                params.encoding,
+            )
+        })
+        .collect::<Vec<bucket::Validator>>();
+
+    let mut k = params.k;
+    let mut samples = Vec::new();
+    let mut sorted = Vec::new();
+    for (path, validator) in inputs.iter().zip(validators.iter_mut()) {
+        log::info!("Open input {}", path);
+
+        validator.k = k;
+        let (input_k, names, kmers) = open_input(path, k, params, validator)?;
+        sorted.push((kmers, names.len()));
+
+        if samples.is_empty() || params.merge == cli::Merge::Samples {
+            samples.extend(names);
+        }
+
+        k = Some(input_k);
+    }
+    let k = k.ok_or(error::Error::CantInferK)?;
+
+    if params.presence_only {
+        samples.clear();
+    }
+
+    let mut kmers = bucket::SortedMerge::new(
+        sorted,
+        params.merge,
+        bucket::Merger::new(params.duplicate, params.presence_only, params.encoding),
+        k,
+    )?;
+
+    let width = params.count_width;
+    let mut writers = create_writers(params, outputs, inputs, k, &samples, width)?;
+    let mut summaries = Vec::new();
+    summaries.resize_with(writers.len(), Summary::default);
+
+    // kmers of a window present in both orientation are merged
+    let mut merger = bucket::Merger::new(params.duplicate, params.presence_only, params.encoding);
+    loop {
+        let (bob, mini2kmers) = bucket::build(
+            kmers.by_ref().take(params.buffer_size),
+            k,
+            params.m,
+            &mut merger,
+        )?;
+        if mini2kmers.is_empty() {
+            break;
+        }
+
+        log::info!("Write window of {} buckets", mini2kmers.len());
+        count_width(params, bucket::max_count(mini2kmers.values()))?;
+
+        write_buckets(
+            &mut writers,
+            &mut summaries,
+            bob,
+            &mini2kmers,
+            k,
+            params,
+            width,
+        )?;
+    }
+
+    merger.collisions += kmers.merger.collisions;
+    drop(kmers);
+
+    for validator in validators.iter() {
+        validator.log_summary();
+    }
+    merger.log_summary();
+
+    finish(params, writers, &summaries)?;
+
+    Ok(summaries)
+}
+
+/// Output kff writer and index of its sections
+type Writer<W> = (kff::Writer<index::Output<W>>, index::Index<W>);
+
+/// Open a kff writer by output, all writers have same header metadata and variables
+fn create_writers<W: std::io::Write>(
+    params: &cli::Command,
+    outputs: Vec<W>,
+    inputs: &[String],
+    k: u8,
+    samples: &[String],
+    width: usize,
+) -> Result<Vec<Writer<W>>> {
+    let metadata = metadata::build(params, inputs, k, samples, width)?;
+
+    outputs
+        .into_iter()
+        .map(|output| create_writer(params, output, &metadata, k, samples, width))
+        .collect()
+}
+
+/// Write minimizer table, index and footer, if it's required, and flush each output
+fn finish<W: std::io::Write>(
+    params: &cli::Command,
+    writers: Vec<Writer<W>>,
+    summaries: &[Summary],
+) -> Result<()> {
+    for ((mut writer, mut index), summary) in writers.into_iter().zip(summaries) {
+        if params.index {
+            let minimizers = index.minimizers();
+            if !minimizers.is_empty() {
+                index.add(b'v', None);
+                for (name, position) in minimizers {
+                    writer.variables().insert(name, position);
+                }
+                writer.write_variables()?;
+            }
+
+            index.write(summary.kmers as u64)?;
+        }
+        index.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Open kff output, write header metadata and variables
+fn create_writer<W: std::io::Write>(
+    params: &cli::Command,
+    output: W,
+    metadata: &str,
+    k: u8,
+    samples: &[String],
+    width: usize,
+) -> Result<Writer<W>> {
+    let output = index::Output::new(output);
+    let index = index::Index::new(output.clone());
+
+    let mut writer = kff::Writer::new(output, params.encoding.kff(), metadata.as_bytes())?;
+
+    writer.variables().insert("k".to_string(), k as u64);
     writer.variables().insert("m".to_string(), params.m as u64);
     writer.variables().insert("max".to_string(), 255);
-    writer.variables().insert("data_size".to_string(), 1);
+    writer
+        .variables()
+        .insert("data_size".to_string(), (samples.len() * width) as u64);
 
     writer.write_variables()?;
 
-    // iterate over bucket
-    for b_id in bob.iter() {
+    Ok((writer, index))
+}
+
+/// Write buckets by increasing minimizer in shard of their minimizer, bucket of multiple minimizer is write at the end
+fn write_buckets<W: std::io::Write>(
+    writers: &mut [Writer<W>],
+    summaries: &mut [Summary],
+    bob: rustc_hash::FxHashSet<u128>,
+    mini2kmers: &bucket::Buckets,
+    k: u8,
+    params: &cli::Command,
+    width: usize,
+) -> Result<()> {
+    let mut b_ids = bob.into_iter().collect::<Vec<u128>>();
+    b_ids.sort_unstable();
+    for b_id in b_ids.iter() {
         log::info!("Compress bucket {}", b_id);
 
         let bucket = mini2kmers
             .get(&format!("{}", b_id))
             .with_context(|| "minimizer id isn't in bucket")?;
-        let mut seens = rustc_hash::FxHashSet::default();
 
-        let mut mini_poss = Vec::new();
-        let mut sequences = Vec::new();
-        let mut datas = Vec::new();
+        let shard = shard::shard(*b_id, writers.len());
+        write_bucket(
+            &mut writers[shard],
+            *b_id,
+            bucket,
+            k,
+            params.m,
+            width,
+            params.encoding,
+        )?;
 
-        let mut fusion = Vec::new();
-        for kmer in bucket.keys() {
-            let mut counts: Vec<u8> = Vec::new();
+        summaries[shard].kmers += bucket.len();
+        summaries[shard].buckets += 1;
+    }
 
-            if seens.contains(kmer) {
-                continue;
-            }
-            seens.insert(*kmer);
-
-            let mut current: u128 = *kmer;
-            while let Some((pred, nuc)) = predecessor(current, params.k, &bucket, &mut seens) {
-                current = pred;
-                fusion.push(nuc);
-                seens.insert(current);
-                counts.extend(
-                    &bucket
-                        .get(&current)
-                        .ok_or_else(|| anyhow!("counts conversion"))?
-                        .to_le_bytes(),
-                );
-            }
+    log::info!("Write bucket of multiple minimizer");
+    if let Some(bucket) = mini2kmers.get("multiple") {
+        write_multiples(writers, summaries, bucket, k, params, width)?;
+    }
 
-            fusion.reverse();
-            counts.reverse();
+    Ok(())
+}
 
-            current = *kmer;
-            fusion.extend(seq2bits::kmer2seq(current, params.k).bytes());
-            counts.extend(
-                &bucket
+/// Compact kmers of a bucket in superkmers and write them in a minimizer section
+fn write_bucket<W: std::io::Write>(
+    (writer, index): &mut Writer<W>,
+    b_id: u128,
+    bucket: &bucket::Bucket,
+    k: u8,
+    m: u8,
+    width: usize,
+    encoding: seq2bits::Encoding,
+) -> Result<()> {
+    let mut seens = rustc_hash::FxHashSet::default();
+
+    let mut mini_poss = Vec::new();
+    let mut sequences = Vec::new();
+    let mut datas = Vec::new();
+
+    let mut fusion = Vec::new();
+    for kmer in bucket.keys() {
+        let mut counts: Vec<&bucket::Counts> = Vec::new();
+
+        if seens.contains(kmer) {
+            continue;
+        }
+        seens.insert(*kmer);
+
+        let mut current: u128 = *kmer;
+        while let Some((pred, nuc)) = predecessor(current, k, bucket, &mut seens, encoding) {
+            current = pred;
+            fusion.push(nuc);
+            seens.insert(current);
+            counts.push(
+                bucket
                     .get(&current)
-                    .ok_or_else(|| anyhow!("counts conversion"))?
-                    .to_le_bytes(),
+                    .ok_or_else(|| anyhow!("counts conversion"))?,
             );
+        }
 
-            while let Some((succ, nuc)) = successor(current, params.k, &bucket, &mut seens) {
-                current = succ;
-                fusion.push(nuc);
-                seens.insert(current);
-                counts.push(
-                    *bucket
-                        .get(&current)
-                        .ok_or_else(|| anyhow!("counts conversion"))?,
-                );
-            }
-
-            let mini_pos = String::from_utf8(fusion.clone())?
-                .find(&seq2bits::kmer2seq(*b_id, params.m))
-                .unwrap() as u64;
-            mini_poss.push(mini_pos);
+        fusion.reverse();
+        counts.reverse();
+
+        current = *kmer;
+        fusion.extend(encoding.kmer2seq(current, k).bytes());
+        counts.push(
+            bucket
+                .get(&current)
+                .ok_or_else(|| anyhow!("counts conversion"))?,
+        );
+
+        while let Some((succ, nuc)) = successor(current, k, bucket, &mut seens, encoding) {
+            current = succ;
+            fusion.push(nuc);
+            seens.insert(current);
+            counts.push(
+                bucket
+                    .get(&current)
+                    .ok_or_else(|| anyhow!("counts conversion"))?,
+            );
+        }
 
-            let mut tmp = Vec::new();
-            tmp.extend(&fusion[0..mini_pos as usize]);
-            tmp.extend(&fusion[(mini_pos as usize + params.m as usize)..]);
+        let mini_pos = String::from_utf8(fusion.clone())?
+            .find(&encoding.kmer2seq(b_id, m))
+            .unwrap() as u64;
+        mini_poss.push(mini_pos);
 
-            sequences.push(tmp);
-            datas.push(counts);
+        let mut tmp = Vec::new();
+        tmp.extend(&fusion[0..mini_pos as usize]);
+        tmp.extend(&fusion[(mini_pos as usize + m as usize)..]);
 
-            fusion.clear();
+        sequences.push(tmp);
+        let mut data = Vec::new();
+        for count in counts {
+            bucket::encode(count, width, &mut data);
         }
+        datas.push(data);
 
-        log::info!("Write bucket {}", b_id);
-        writer.write_minimizer_seq_section(
-            &seq2bits::kmer2seq(*b_id, params.m).into_bytes(),
-            &mini_poss[..],
-            &sequences[..],
-            &datas,
-        )?;
+        fusion.clear();
     }
 
-    log::info!("Write bucket of multiple minimizer");
-    if mini2kmers.contains_key("multiple") {
-        let bucket = mini2kmers.get("multiple").unwrap();
-        let mut sequences = Vec::new();
-        let mut datas = Vec::new();
-
-        for kmer in bucket.keys() {
-            sequences.push(seq2bits::kmer2seq(*kmer, params.k));
-            datas.push([*bucket
-                .get(&kmer)
-                .ok_or_else(|| anyhow!("counts not present"))?])
+    log::info!("Write bucket {}", b_id);
+    let minimizer = encoding.kmer2seq(b_id, m);
+    index.add(b'm', Some(minimizer.clone()));
+    writer.write_minimizer_seq_section(
+        &minimizer.into_bytes(),
+        &mini_poss[..],
+        &sequences[..],
+        &datas,
+    )?;
+
+    Ok(())
+}
+
+/// Write kmers of multiple minimizer bucket in a raw section of each shard
+fn write_multiples<W: std::io::Write>(
+    writers: &mut [Writer<W>],
+    summaries: &mut [Summary],
+    bucket: &bucket::Bucket,
+    k: u8,
+    params: &cli::Command,
+    width: usize,
+) -> Result<()> {
+    if writers.len() == 1 {
+        summaries[0].kmers += bucket.len();
+
+        return write_multiple(&mut writers[0], bucket, k, width, params.encoding);
+    }
+
+    let parts = shard::split(bucket, k, params.m, writers.len(), params.encoding);
+    for ((writer, summary), part) in writers.iter_mut().zip(summaries).zip(parts) {
+        if !part.is_empty() {
+            summary.kmers += part.len();
+            write_multiple(writer, &part, k, width, params.encoding)?;
         }
+    }
+
+    Ok(())
+}
 
-        writer.write_raw_seq_section(&sequences[..], &datas[..])?;
+/// Write kmers of multiple minimizer bucket in a raw section
+fn write_multiple<W: std::io::Write>(
+    (writer, index): &mut Writer<W>,
+    bucket: &bucket::Bucket,
+    k: u8,
+    width: usize,
+    encoding: seq2bits::Encoding,
+) -> Result<()> {
+    let mut sequences = Vec::new();
+    let mut datas = Vec::new();
+
+    for kmer in bucket.keys() {
+        sequences.push(encoding.kmer2seq(*kmer, k));
+        let mut data = Vec::new();
+        bucket::encode(
+            bucket
+                .get(&kmer)
+                .ok_or_else(|| anyhow!("counts not present"))?,
+            width,
+            &mut data,
+        );
+        datas.push(data);
     }
 
+    index.add(b'r', None);
+    writer.write_raw_seq_section(&sequences[..], &datas[..])?;
+
     Ok(())
 }
 
-fn clean_temp_file(minis: rustc_hash::FxHashSet<u128>, prefix: &str) -> Result<()> {
-    for mini in minis {
-        std::fs::remove_file(format!("{}{}", prefix, mini))?;
+/// Open an input and check its kmer size, return kmer size, sample names and kmers
+fn open_input<'a>(
+    path: &str,
+    k: Option<u8>,
+    params: &cli::Command,
+    validator: &'a mut bucket::Validator,
+) -> Result<(u8, Vec<String>, bucket::Kmers<'a>)> {
+    let mut samples = vec![path.to_string()];
+
+    let (k, kmers): (u8, bucket::Kmers) = match params.format {
+        cli::Format::Csv => {
+            let (k, reader) = bucket::csv(path, params, validator)?;
+            cli::check_k_m(k, params.m)?;
+
+            (k, Box::new(bucket::single(reader)))
+        }
+        cli::Format::Matrix => {
+            let (k, names, reader) = bucket::matrix(path, params, validator)?;
+            cli::check_k_m(k, params.m)?;
+            samples = names;
+
+            (k, Box::new(reader))
+        }
+        cli::Format::Kmc => {
+            let reader = kmc::Reader::from_path(path, params.encoding)?;
+            let k = input_k(reader.header().k as u64, k, params.m)?;
+
+            (k, Box::new(bucket::single(reader)))
+        }
+        cli::Format::Jellyfish => {
+            let reader = jellyfish::Reader::from_path(path, params.encoding)?;
+            let k = input_k(reader.header().k(), k, params.m)?;
+
+            (k, Box::new(bucket::single(reader)))
+        }
+        cli::Format::Fasta | cli::Format::Fastq => {
+            let k = k.ok_or(error::Error::CliKRequired)?;
+            let records = fastx::Reader::new(input::open(path)?);
+            let kmers = count::count(records, k, params.min_abundance, params.encoding)?;
+
+            (k, Box::new(bucket::single(kmers.into_iter().map(Ok))))
+        }
+        cli::Format::Unitig => {
+            let k = k.ok_or(error::Error::CliKRequired)?;
+            let records = fastx::Reader::new(input::open(path)?);
+
+            (
+                k,
+                Box::new(bucket::single(unitig::kmers(records, k, params.encoding))),
+            )
+        }
+        cli::Format::Gfa => {
+            let k = k.ok_or(error::Error::CliKRequired)?;
+            let kmers = gfa::kmers(input::open(path)?, k, params.encoding);
+
+            (k, Box::new(bucket::single(kmers)))
+        }
+        cli::Format::Kff => {
+            let input = kff_input::read(path, params.encoding)?;
+            let k = match input.k {
+                Some(size) => input_k(size as u64, k, params.m)?,
+                None => k.ok_or(error::Error::CantInferK)?,
+            };
+            samples = if input.samples.len() == input.nb_samples {
+                input.samples
+            } else {
+                (1..=input.nb_samples)
+                    .map(|i| format!("{}:{}", path, i))
+                    .collect()
+            };
+
+            (k, Box::new(input.kmers.into_iter().map(Ok)))
+        }
+        cli::Format::Binary => {
+            let k = k.ok_or(error::Error::CliKRequired)?;
+            let reader = binary::Reader::from_path(path, k, params)?;
+
+            (k, Box::new(bucket::single(reader)))
+        }
+    };
+
+    Ok((k, samples, kmers))
+}
+
+/// Choose count width according to overflow policy
+fn count_width(params: &cli::Command, max_count: u64) -> Result<usize> {
+    let width = params.count_width;
+
+    if max_count <= bucket::max_value(width) {
+        return Ok(width);
     }
 
-    if std::path::Path::new(&format!("{}multiple", prefix)).exists() {
-        std::fs::remove_file(format!("{}multiple", prefix))?;
+    match params.overflow {
+        cli::Overflow::Error => Err(error::Error::CountOverflow {
+            count: max_count,
+            width,
+        }
+        .into()),
+        cli::Overflow::Saturate => {
+            log::warn!(
+                "Maximal count {} is saturated to {}",
+                max_count,
+                bucket::max_value(width)
+            );
+            Ok(width)
+        }
+        cli::Overflow::AutoWiden => {
+            let new_width = [1, 2, 4, 8]
+                .iter()
+                .copied()
+                .find(|w| *w >= width && max_count <= bucket::max_value(*w))
+                .unwrap_or(8);
+            log::info!(
+                "Count width is widen from {} to {} bytes to store maximal count {}",
+                width,
+                new_width,
+                max_count
+            );
+            Ok(new_width)
+        }
     }
+}
 
-    Ok(())
+/// Kmer size of input must match kmer size parameter if it's set
+fn input_k(input: u64, k: Option<u8>, m: u8) -> Result<u8> {
+    match k {
+        Some(param) if input != param as u64 => {
+            Err(error::Error::InputKDiffThanParams { input, param }.into())
+        }
+        _ => {
+            let k = cli::kmer_size(input)?;
+            cli::check_k_m(k, m)?;
+            Ok(k)
+        }
+    }
 }
 
 fn predecessor(
     kmer: u128,
     k: u8,
-    set: &rustc_hash::FxHashMap<u128, u8>,
+    set: &bucket::Bucket,
     seens: &mut rustc_hash::FxHashSet<u128>,
+    encoding: seq2bits::Encoding,
 ) -> Option<(u128, u8)> {
     let sub = kmer >> 2;
 
@@ -156,7 +764,7 @@ fn predecessor(
         let pred = ((nuc as u128) << ((k - 1) * 2)) ^ sub;
 
         if !seens.contains(&pred) && set.contains_key(&pred) {
-            return Some((pred, seq2bits::bit2nuc(nuc)));
+            return Some((pred, encoding.bit2nuc(nuc)));
         }
     }
 
@@ -166,8 +774,9 @@ fn predecessor(
 fn successor(
     kmer: u128,
     k: u8,
-    set: &rustc_hash::FxHashMap<u128, u8>,
+    set: &bucket::Bucket,
     seens: &mut rustc_hash::FxHashSet<u128>,
+    encoding: seq2bits::Encoding,
 ) -> Option<(u128, u8)> {
     let mask = (2_u128.pow(k as u32 * 2) - 1) >> 2;
     let sub = (kmer & mask) << 2;
@@ -176,7 +785,7 @@ fn successor(
         let succ = sub ^ nuc as u128;
 
         if !seens.contains(&succ) && set.contains_key(&succ) {
-            return Some((succ, seq2bits::bit2nuc(nuc)));
+            return Some((succ, encoding.bit2nuc(nuc)));
         }
     }
 
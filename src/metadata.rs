@@ -0,0 +1,175 @@
+//! Build metadata of kff header, one `key=value` entry by line.
+//!
+//! Metadata record how the file was produced: tool version, command line (each argument is quoted if it's required to paste it in a shell), kmer and minimizer size, count width (number of bytes of each count in kmer data), minimizer hash, timestamp (seconds since unix epoch) and name, size and crc32 of each input file. Crc32 is computed while input is parsed, so it's omitted in streaming mode (header is written before inputs are read) and for stdin. Quantization scheme, sample names and entries set by user with `--metadata` follow.
+
+/* crate use */
+use anyhow::{Context, Result};
+
+/* local use */
+use crate::cli;
+use crate::input;
+
+/// Build metadata of kff header
+pub fn build(
+    params: &cli::Command,
+    inputs: &[String],
+    k: u8,
+    samples: &[String],
+    width: usize,
+) -> Result<String> {
+    let mut metadata = String::new();
+
+    metadata.push_str(&format!("tool={}\n", env!("CARGO_PKG_NAME")));
+    metadata.push_str(&format!("version={}\n", env!("CARGO_PKG_VERSION")));
+    metadata.push_str(&format!(
+        "command={}\n",
+        std::env::args()
+            .map(|arg| quote(&arg))
+            .collect::<Vec<String>>()
+            .join(" ")
+    ));
+    metadata.push_str(&format!("k={}\n", k));
+    metadata.push_str(&format!("m={}\n", params.m));
+    metadata.push_str(&format!("count_width={}\n", width));
+    metadata.push_str("minimizer_hash=murmur3_x64_128\n");
+    metadata.push_str(&format!("timestamp={}\n", timestamp()));
+
+    for path in inputs.iter() {
+        metadata.push_str(&format!("input={}\n", path));
+
+        // stdin or database with many files (KMC) have no size and crc32
+        if std::path::Path::new(path).is_file() {
+            let size = std::fs::metadata(path)
+                .with_context(|| format!("Read size of {}", path))?
+                .len();
+            metadata.push_str(&format!("input_size={}\n", size));
+            if let Some(crc) = input::crc32(path).filter(|_| !params.streaming) {
+                metadata.push_str(&format!("input_crc32={:08x}\n", crc));
+            }
+        }
+    }
+
+    if params.quantize != cli::Quantize::None {
+        metadata.push_str(&format!("quantization={}\n", params.quantize));
+    }
+    // sample names of matrix or kff input, or of each input merged as a sample
+    if matches!(params.format, cli::Format::Matrix | cli::Format::Kff) || samples.len() > 1 {
+        for name in samples.iter() {
+            metadata.push_str(&format!("sample={}\n", name));
+        }
+    }
+
+    for entry in params.metadata.iter() {
+        metadata.push_str(&format!("{}={}\n", entry.key, entry.value));
+    }
+
+    Ok(metadata)
+}
+
+/// Number of seconds since unix epoch
+fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Quote argument with single quote if it contains a character interpreted by shell
+fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use clap::Clap;
+
+    #[test]
+    fn build_() {
+        let mut params = cli::Command::parse_from(&[
+            "kmers2kff",
+            "-i",
+            "tests/data/test.csv",
+            "-o",
+            "test.kff",
+            "-m",
+            "6",
+            "-p",
+            "tests",
+            "--metadata",
+            "lab=bioinfo",
+            "--metadata",
+            "note=a=b",
+        ]);
+        let inputs = vec!["tests/data/test.csv".to_string(), "-".to_string()];
+
+        // crc32 is computed while input is read
+        let metadata = build(&params, &inputs, 11, &[], 2).unwrap();
+        assert!(!metadata.contains("input_crc32="));
+        std::io::copy(
+            &mut input::open("tests/data/test.csv").unwrap(),
+            &mut std::io::sink(),
+        )
+        .unwrap();
+
+        let metadata = build(&params, &inputs, 11, &[], 2).unwrap();
+        let lines = metadata.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[0], "tool=kmers2kff");
+        assert!(lines.contains(&"k=11"));
+        assert!(lines.contains(&"m=6"));
+        assert!(lines.contains(&"count_width=2"));
+        assert!(lines.contains(&"minimizer_hash=murmur3_x64_128"));
+
+        let size = std::fs::metadata("tests/data/test.csv").unwrap().len();
+        let position = lines
+            .iter()
+            .position(|line| *line == "input=tests/data/test.csv")
+            .unwrap();
+        assert_eq!(lines[position + 1], format!("input_size={}", size));
+        assert_eq!(
+            lines[position + 2],
+            format!(
+                "input_crc32={:08x}",
+                input::crc32("tests/data/test.csv").unwrap()
+            )
+        );
+        assert_eq!(lines[position + 3], "input=-");
+
+        assert_eq!(&lines[lines.len() - 2..], &["lab=bioinfo", "note=a=b"]);
+
+        // header is written before inputs are read in streaming mode
+        params.streaming = true;
+        let metadata = build(&params, &inputs, 11, &[], 2).unwrap();
+        assert!(!metadata.contains("input_crc32="));
+    }
+
+    #[test]
+    fn metadata_entry() {
+        assert!("key".parse::<cli::MetadataEntry>().is_err());
+        assert!("=value".parse::<cli::MetadataEntry>().is_err());
+        assert_eq!(
+            "key=".parse::<cli::MetadataEntry>().unwrap(),
+            cli::MetadataEntry {
+                key: "key".to_string(),
+                value: "".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn quote_() {
+        assert_eq!(quote("tests/data/test.csv"), "tests/data/test.csv");
+        assert_eq!(quote("out.{shard}.kff"), "'out.{shard}.kff'");
+        assert_eq!(quote("my file"), "'my file'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote(""), "''");
+    }
+}
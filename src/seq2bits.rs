@@ -1,85 +1,216 @@
 //! A set of function to convert small sequence (less than 32 nucleotide) in 2 bit representation.
+//! With [ACTG](Encoding::ACTG) encoding:
 //! - A or a -> 00
 //! - C or c -> 01
 //! - T or t -> 10
 //! - G or g -> 11
 //!
-//! We use the second and thrid bit of each value provide, if you provide no ACTG value this function silently convert to A, C, T or G, for exemple N or n is convert in G.
+//! We use the second and thrid bit of each value provide, if you provide no ACTG value this function silently convert to A, C, T or G, for exemple N or n is convert in G. Use [first_invalid](first_invalid) to check sequence before conversion.
 //!
-//! With this coding and if kmer size is odd, if the popcount of forward is odd the popcount of reverse is even. In this library if a kmer have even popcount is the canonical kmer.
+//! Other encoding could be used by build an [Encoding](Encoding) from nucleotides associate to value 0, 1, 2 and 3, conversion depend on encoding are method of [Encoding](Encoding), the same encoding must be used for all kmers of a run. Default encoding is [ACGT](Encoding::ACGT) (A=00, C=01, G=10, T=11).
+//!
+//! With ACTG coding and if kmer size is odd, if the popcount of forward is odd the popcount of reverse is even. In this library if a kmer have even popcount is the canonical kmer.
 //!
 //! If we work only with canonical kmer, we can remove one bit at any extremity. To reconstruct lost bit, if result have even popcount we add a 0, if it's ood we add 1.
 //!
 //! This 2bit coding is inspired by https://cs.stackexchange.com/questions/82644/compact-mapping-from-an-involuted-set
 
+/* crate use */
 use fasthash::FastHash;
 
-/// Convert a sequence in 2 bit representation if suseq is larger than 32 only the last 32 nuc is store
+/// Index of nucleotide in lookup table, second and third bit of nucleotide (A=0, C=1, T=2, G=3)
 #[inline(always)]
-pub fn seq2bit(subseq: &[u8]) -> u128 {
-    let mut kmer: u128 = 0;
+fn index(nuc: u8) -> usize {
+    ((nuc >> 1) & 0b11) as usize
+}
+
+/// A 2 bit encoding of nucleotides
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Encoding {
+    /// Value of each nucleotide, index by [index](index)
+    values: [u8; 4],
+    /// Nucleotide of each value
+    nucs: [u8; 4],
+    /// Xor mask that complement all nucleotides of a kmer
+    comp: u128,
+}
+
+impl Encoding {
+    /// Default encoding A=00, C=01, T=10, G=11
+    pub const ACTG: Encoding = Encoding {
+        values: [0, 1, 2, 3],
+        nucs: *b"ACTG",
+        comp: 0xAAAA_AAAA_AAAA_AAAA_AAAA_AAAA_AAAA_AAAA,
+    };
+
+    /// Encoding A=00, C=01, G=10, T=11 (used by KMC or Jellyfish)
+    pub const ACGT: Encoding = Encoding {
+        values: [0, 1, 3, 2],
+        nucs: *b"ACGT",
+        comp: 0xFFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF_FFFF,
+    };
+
+    /// Build encoding from nucleotides associate to value 0, 1, 2 and 3
+    pub fn new(order: &[u8; 4]) -> Self {
+        let mut values = [0; 4];
+        let mut nucs = [0; 4];
+        for (value, nuc) in order.iter().enumerate() {
+            values[index(*nuc)] = value as u8;
+            nucs[value] = nuc.to_ascii_uppercase();
+        }
 
-    for n in subseq {
-        kmer <<= 2;
-        kmer |= nuc2bit(*n);
+        // in any encoding complement of A is T and C is G, so xor value of A and T complement each nucleotide
+        let comp = (values[index(b'A')] ^ values[index(b'T')]) as u128
+            * 0x5555_5555_5555_5555_5555_5555_5555_5555;
+
+        Encoding { values, nucs, comp }
     }
 
-    kmer
-}
+    /// KFF encoding byte, value of A, C, G and T on 2 bits from most to least significant bits
+    pub fn kff(&self) -> u8 {
+        self.values[index(b'A')] << 6
+            | self.values[index(b'C')] << 4
+            | self.values[index(b'G')] << 2
+            | self.values[index(b'T')]
+    }
 
-/// Convert a nucleotide in 2bit representation, by use conversion present in [seq2bit](seq2bit)
-#[inline(always)]
-pub fn nuc2bit(nuc: u8) -> u128 {
-    (nuc as u128 >> 1) & 0b11
-}
+    /// Convert a sequence in 2 bit representation if suseq is larger than 32 only the last 32 nuc is store
+    #[inline(always)]
+    pub fn seq2bit(&self, subseq: &[u8]) -> u128 {
+        let mut kmer: u128 = 0;
 
-/// Convert a 2 bit repersentation in String.
-#[inline(always)]
-pub fn kmer2seq(mut kmer: u128, k: u8) -> String {
-    let mut buffer: [u8; 64] = [0; 64];
+        for n in subseq {
+            kmer <<= 2;
+            kmer |= self.nuc2bit(*n);
+        }
 
-    for i in (0..k).rev() {
-        buffer[i as usize] = bit2nuc(kmer & 0b11);
+        kmer
+    }
 
-        kmer >>= 2;
+    /// Convert a nucleotide in 2bit representation, by use conversion present in [seq2bit](Encoding::seq2bit)
+    #[inline(always)]
+    pub fn nuc2bit(&self, nuc: u8) -> u128 {
+        self.values[index(nuc)] as u128
     }
 
-    unsafe { String::from_utf8_unchecked((&buffer[..k as usize]).to_vec()) }
-}
+    /// Convert a kmer in 2 bit representation with A=00, C=01, G=10, T=11 (used by KMC or Jellyfish) in [seq2bit](Encoding::seq2bit) representation
+    #[inline(always)]
+    pub fn acgt2bit(&self, kmer: u128, k: u8) -> u128 {
+        if *self == Encoding::ACGT {
+            kmer
+        } else if *self == Encoding::ACTG {
+            kmer ^ ((kmer >> 1) & 0x5555_5555_5555_5555_5555_5555_5555_5555)
+        } else {
+            let mut res = 0;
+            for i in (0..k).rev() {
+                res = (res << 2) | self.nuc2bit(b"ACGT"[((kmer >> (i * 2)) & 0b11) as usize]);
+            }
 
-/// Convert the 2bit representation of a nucleotide in nucleotide
-#[inline(always)]
-pub fn bit2nuc(bit: u128) -> u8 {
-    match bit {
-        0 => b'A',
-        1 => b'C',
-        2 => b'T',
-        3 => b'G',
-        _ => b'G',
+            res
+        }
     }
-}
 
-/// Take a kmer and return the canonical form
-#[inline(always)]
-pub fn canonical(kmer: u128, k: u8) -> (u128, bool) {
-    let rev = revcomp(kmer, k);
+    /// Convert a kmer in [seq2bit](Encoding::seq2bit) representation in 2 bit representation with A=00, C=01, G=10, T=11, order of results is lexicographic order of kmers
+    #[inline(always)]
+    pub fn bit2acgt(&self, kmer: u128, k: u8) -> u128 {
+        if *self == Encoding::ACGT {
+            kmer
+        } else if *self == Encoding::ACTG {
+            kmer ^ ((kmer >> 1) & 0x5555_5555_5555_5555_5555_5555_5555_5555)
+        } else {
+            let mut res = 0;
+            for i in (0..k).rev() {
+                res = (res << 2) | Encoding::ACGT.nuc2bit(self.bit2nuc(kmer >> (i * 2)));
+            }
+
+            res
+        }
+    }
+
+    /// Convert a 2 bit repersentation in String.
+    #[inline(always)]
+    pub fn kmer2seq(&self, mut kmer: u128, k: u8) -> String {
+        let mut buffer: [u8; 64] = [0; 64];
+
+        for i in (0..k).rev() {
+            buffer[i as usize] = self.bit2nuc(kmer & 0b11);
+
+            kmer >>= 2;
+        }
+
+        unsafe { String::from_utf8_unchecked((&buffer[..k as usize]).to_vec()) }
+    }
+
+    /// Convert the 2bit representation of a nucleotide in nucleotide
+    #[inline(always)]
+    pub fn bit2nuc(&self, bit: u128) -> u8 {
+        self.nucs[(bit & 0b11) as usize]
+    }
+
+    /// Take a kmer and return the canonical form
+    #[inline(always)]
+    pub fn canonical(&self, kmer: u128, k: u8) -> (u128, bool) {
+        let rev = self.revcomp(kmer, k);
+
+        if kmer < rev {
+            (kmer, true)
+        } else {
+            (rev, false)
+        }
+    }
+
+    /// Return the reverse complement of kmer
+    #[inline(always)]
+    pub fn revcomp(&self, kmer: u128, k: u8) -> u128 {
+        rev(kmer ^ self.comp, k)
+    }
+
+    /// return minimizer of kmer and position
+    pub fn get_minimizer(&self, mut kmer: u128, k: u8, m: u8) -> (u128, usize, bool) {
+        let max_len = (k - m + 1) as usize;
+        let mask = (1 << (m * 2)) - 1;
+
+        let mut score = u128::max_value();
+        let mut minimizer = 0;
+        let mut position = 0;
+        let mut forward = true;
+
+        for i in 0..max_len {
+            let rb_index = (max_len - i - 1) as usize;
 
-    if kmer < rev {
-        (kmer, true)
-    } else {
-        (rev, false)
+            let (mini, local_forward) = self.canonical(kmer & mask, m);
+
+            let local_score = hash(mini);
+
+            if local_score < score {
+                score = local_score;
+                position = rb_index;
+                minimizer = mini;
+                forward = local_forward;
+            }
+
+            kmer >>= 2;
+        }
+
+        (minimizer, position, forward)
     }
 }
 
-/// Return the reverse complement of kmer
-#[inline(always)]
-pub fn revcomp(kmer: u128, k: u8) -> u128 {
-    rev(speed_comp(kmer), k)
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::ACGT
+    }
 }
 
+/// Return true if nucleotide is A, C, G or T (upper or lower case)
 #[inline(always)]
-fn speed_comp(kmer: u128) -> u128 {
-    kmer ^ 0xAAAA_AAAA_AAAA_AAAA_AAAA_AAAA_AAAA_AAAA
+pub fn is_valid(nuc: u8) -> bool {
+    matches!(nuc, b'A' | b'C' | b'G' | b'T' | b'a' | b'c' | b'g' | b't')
+}
+
+/// Return position of the first nucleotide isn't A, C, G or T
+pub fn first_invalid(subseq: &[u8]) -> Option<usize> {
+    subseq.iter().position(|nuc| !is_valid(*nuc))
 }
 
 /// Return the reverse of kmer
@@ -102,34 +233,10 @@ pub fn rev(mut kmer: u128, k: u8) -> u128 {
     kmer >> (128 - k * 2)
 }
 
-/// return minimizer of kmer and position
-pub fn get_minimizer(mut kmer: u128, k: u8, m: u8) -> (u128, usize, bool) {
-    let max_len = (k - m + 1) as usize;
-    let mask = (1 << (m * 2)) - 1;
-
-    let mut score = u128::max_value();
-    let mut minimizer = 0;
-    let mut position = 0;
-    let mut forward = true;
-
-    for i in 0..max_len {
-        let rb_index = (max_len - i - 1) as usize;
-
-        let (mini, local_forward) = canonical(kmer & mask, m);
-
-        let local_score = fasthash::murmur3::Hash128_x64::hash(mini.to_be_bytes());
-
-        if local_score < score {
-            score = local_score;
-            position = rb_index;
-            minimizer = mini;
-            forward = local_forward;
-        }
-
-        kmer >>= 2;
-    }
-
-    (minimizer, position, forward)
+/// Hash of minimizer used to select minimizer of a kmer
+#[inline(always)]
+pub fn hash(minimizer: u128) -> u128 {
+    fasthash::murmur3::Hash128_x64::hash(minimizer.to_be_bytes())
 }
 
 pub fn multiple_mini(mut kmer: u128, minimizer: u128, k: u8, m: u8) -> bool {
@@ -155,25 +262,68 @@ pub fn multiple_mini(mut kmer: u128, minimizer: u128, k: u8, m: u8) -> bool {
 mod test {
     use super::*;
 
+    const ACTG: Encoding = Encoding::ACTG;
+
     #[test]
     fn seq2bit_() {
         // TAGGC -> 1000111101
-        assert_eq!(seq2bit(b"TAGGC"), 0b1000111101);
+        assert_eq!(ACTG.seq2bit(b"TAGGC"), 0b1000111101);
 
         // GCCTA -> 110101100
-        assert_eq!(seq2bit(b"GCCTA"), 0b1101011000);
+        assert_eq!(ACTG.seq2bit(b"GCCTA"), 0b1101011000);
+    }
+
+    #[test]
+    fn acgt2bit_() {
+        // TAGGC in ACGT order -> 1100101001 -> TAGGC
+        assert_eq!(ACTG.acgt2bit(0b1100101001, 5), ACTG.seq2bit(b"TAGGC"));
+    }
+
+    #[test]
+    fn bit2acgt_() {
+        assert_eq!(ACTG.bit2acgt(ACTG.seq2bit(b"TAGGC"), 5), 0b1100101001);
+
+        // A < C < G < T
+        assert!(ACTG.bit2acgt(ACTG.seq2bit(b"AG"), 2) < ACTG.bit2acgt(ACTG.seq2bit(b"AT"), 2));
+    }
+
+    #[test]
+    fn encoding_() {
+        assert_eq!(Encoding::new(b"ACTG"), ACTG);
+        assert_eq!(Encoding::new(b"acgt"), Encoding::ACGT);
+
+        assert_eq!(ACTG.kff(), 0b00011110);
+        assert_eq!(Encoding::ACGT.kff(), 0b00011011);
+
+        let tgca = Encoding::new(b"TGCA");
+        assert_eq!(tgca.kff(), 0b11100100);
+        assert_eq!(tgca.seq2bit(b"TAGGC"), 0b0011010110);
+        assert_eq!(tgca.kmer2seq(0b0011010110, 5), "TAGGC");
+        assert_eq!(
+            tgca.kmer2seq(tgca.revcomp(tgca.seq2bit(b"TAGGC"), 5), 5),
+            "GCCTA"
+        );
+        assert_eq!(tgca.acgt2bit(0b1100101001, 5), tgca.seq2bit(b"TAGGC"));
+        assert_eq!(tgca.bit2acgt(tgca.seq2bit(b"TAGGC"), 5), 0b1100101001);
+    }
+
+    #[test]
+    fn first_invalid_() {
+        assert_eq!(first_invalid(b"ACGTacgt"), None);
+        assert_eq!(first_invalid(b"ACNT"), Some(2));
+        assert_eq!(first_invalid(b"1CGT"), Some(0));
     }
 
     #[test]
     fn bit2seq_() {
         // 1000111101 -> TAGGC
-        assert_eq!(kmer2seq(0b1000111101, 5), "TAGGC");
+        assert_eq!(ACTG.kmer2seq(0b1000111101, 5), "TAGGC");
 
         // 110101100 -> GCCTA
-        assert_eq!(kmer2seq(0b1101011000, 5), "GCCTA");
+        assert_eq!(ACTG.kmer2seq(0b1101011000, 5), "GCCTA");
 
         assert_eq!(
-            kmer2seq(0b1101011000, 31),
+            ACTG.kmer2seq(0b1101011000, 31),
             "AAAAAAAAAAAAAAAAAAAAAAAAAAGCCTA"
         );
     }
@@ -181,16 +331,16 @@ mod test {
     #[test]
     fn canonical_() {
         // TAGGC -> 1000111101 canonical TAGGC -> 1000111101
-        assert_eq!(canonical(0b1000111101, 5), (0b1000111101, true));
+        assert_eq!(ACTG.canonical(0b1000111101, 5), (0b1000111101, true));
 
         // GCCTA -> 1101011000 canonical TAGGC -> 1000111101
-        assert_eq!(canonical(0b1101011000, 5), (0b1000111101, false));
+        assert_eq!(ACTG.canonical(0b1101011000, 5), (0b1000111101, false));
     }
 
     #[test]
     fn revcomp_() {
         // TAGGC -> 1000111101 revcomp GCCTA -> 1101011000
-        assert_eq!(0b1000111101, revcomp(0b1101011000, 5))
+        assert_eq!(0b1000111101, ACTG.revcomp(0b1101011000, 5))
     }
 
     #[test]
@@ -205,29 +355,31 @@ mod test {
     fn large_kmer() {
         assert_eq!(
             "CGGAGAGCAGAACAGTCTTACTTTCGTGCACAG",
-            kmer2seq(seq2bit(b"CGGAGAGCAGAACAGTCTTACTTTCGTGCACAG"), 33)
+            ACTG.kmer2seq(ACTG.seq2bit(b"CGGAGAGCAGAACAGTCTTACTTTCGTGCACAG"), 33)
         );
         assert_eq!(
             "CTGTGCACGAAAGTAAGACTGTTCTGCTCTCCG",
-            kmer2seq(
-                revcomp(seq2bit(b"CGGAGAGCAGAACAGTCTTACTTTCGTGCACAG"), 33),
+            ACTG.kmer2seq(
+                ACTG.revcomp(ACTG.seq2bit(b"CGGAGAGCAGAACAGTCTTACTTTCGTGCACAG"), 33),
                 33
             )
         );
 
         assert_eq!(
             "AAGCGTTTGCGGGGCGCGGTCCAAAGCATTACACAGGGAGACGTTCGCATGATCTCCCGAATGG",
-            kmer2seq(
-                seq2bit(b"AAGCGTTTGCGGGGCGCGGTCCAAAGCATTACACAGGGAGACGTTCGCATGATCTCCCGAATGG"),
+            ACTG.kmer2seq(
+                ACTG.seq2bit(b"AAGCGTTTGCGGGGCGCGGTCCAAAGCATTACACAGGGAGACGTTCGCATGATCTCCCGAATGG"),
                 64
             )
         );
 
         assert_eq!(
             "CCATTCGGGAGATCATGCGAACGTCTCCCTGTGTAATGCTTTGGACCGCGCCCCGCAAACGCTT",
-            kmer2seq(
-                revcomp(
-                    seq2bit(b"AAGCGTTTGCGGGGCGCGGTCCAAAGCATTACACAGGGAGACGTTCGCATGATCTCCCGAATGG"),
+            ACTG.kmer2seq(
+                ACTG.revcomp(
+                    ACTG.seq2bit(
+                        b"AAGCGTTTGCGGGGCGCGGTCCAAAGCATTACACAGGGAGACGTTCGCATGATCTCCCGAATGG"
+                    ),
                     64
                 ),
                 64
@@ -244,28 +396,28 @@ mod test {
 
         let mut canos = Vec::new();
         for i in 0..(seq.len() + 1 - k as usize) {
-            let kmer = seq2bit(seq[i..(i + k as usize)].as_bytes());
+            let kmer = ACTG.seq2bit(seq[i..(i + k as usize)].as_bytes());
 
-            let (_, _, forward) = get_minimizer(kmer, k, m);
+            let (_, _, forward) = ACTG.get_minimizer(kmer, k, m);
 
             if forward {
                 canos.push(kmer);
             } else {
-                canos.push(revcomp(kmer, k));
+                canos.push(ACTG.revcomp(kmer, k));
             }
         }
 
         assert_eq!(
             &[
-                seq2bit(b"CATAGGT"),
-                seq2bit(b"TCATAGG"),
-                seq2bit(b"TTCATAG"),
-                seq2bit(b"TATGAAC"),
-                seq2bit(b"ATGAACT"),
-                seq2bit(b"TGAACTT"),
-                seq2bit(b"TAAGTTC"),
-                seq2bit(b"AACTTAC"),
-                seq2bit(b"CGTAAGT"),
+                ACTG.seq2bit(b"CATAGGT"),
+                ACTG.seq2bit(b"TCATAGG"),
+                ACTG.seq2bit(b"TTCATAG"),
+                ACTG.seq2bit(b"TATGAAC"),
+                ACTG.seq2bit(b"ATGAACT"),
+                ACTG.seq2bit(b"TGAACTT"),
+                ACTG.seq2bit(b"TAAGTTC"),
+                ACTG.seq2bit(b"AACTTAC"),
+                ACTG.seq2bit(b"CGTAAGT"),
             ],
             &canos[..]
         );
@@ -273,22 +425,25 @@ mod test {
 
     #[test]
     fn multi_mini() {
-        assert_eq!(multiple_mini(seq2bit(b"ACTG"), seq2bit(b"TG"), 4, 2), false);
+        assert_eq!(
+            multiple_mini(ACTG.seq2bit(b"ACTG"), ACTG.seq2bit(b"TG"), 4, 2),
+            false
+        );
 
         assert_eq!(
-            multiple_mini(seq2bit(b"TGACTG"), seq2bit(b"TG"), 6, 2),
+            multiple_mini(ACTG.seq2bit(b"TGACTG"), ACTG.seq2bit(b"TG"), 6, 2),
             true
         );
 
         assert_eq!(
-            multiple_mini(seq2bit(b"TGACTG"), seq2bit(b"TG"), 4, 2),
+            multiple_mini(ACTG.seq2bit(b"TGACTG"), ACTG.seq2bit(b"TG"), 4, 2),
             false
         );
 
         assert_eq!(
             multiple_mini(
-                seq2bit(b"GAGGTACGCGGTTGCCCATCGATATCGGCATG"),
-                seq2bit(b"AGGTACGCGGTTGCCCATCGATATCGGCAT"),
+                ACTG.seq2bit(b"GAGGTACGCGGTTGCCCATCGATATCGGCATG"),
+                ACTG.seq2bit(b"AGGTACGCGGTTGCCCATCGATATCGGCAT"),
                 32,
                 30
             ),
@@ -297,8 +452,8 @@ mod test {
 
         assert_eq!(
             multiple_mini(
-                seq2bit(b"GAGGTACGCGGTTGCCCATCGATATCGGCATG"),
-                seq2bit(b"GG"),
+                ACTG.seq2bit(b"GAGGTACGCGGTTGCCCATCGATATCGGCATG"),
+                ACTG.seq2bit(b"GG"),
                 32,
                 2
             ),
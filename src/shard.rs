@@ -0,0 +1,128 @@
+//! Partition minimizer buckets in shards, each shard contains a range of minimizer hash (same hash as [get_minimizer](crate::seq2bits::Encoding::get_minimizer)).
+
+/* crate use */
+use anyhow::Result;
+
+/* local use */
+use crate::bucket;
+use crate::error::Error;
+use crate::seq2bits;
+
+/// Shard of a minimizer, shard i contains minimizers with hash in [range(i)](range)
+pub fn shard(minimizer: u128, nb_shards: usize) -> usize {
+    (((seq2bits::hash(minimizer) >> 64) * nb_shards as u128) >> 64) as usize
+}
+
+/// First and last minimizer hash of a shard
+pub fn range(shard: usize, nb_shards: usize) -> (u128, u128) {
+    // first value of upper 64 bits of hash in shard i is ceil(i * 2^64 / nb_shards)
+    let start = |i: usize| {
+        if i == 0 {
+            0
+        } else {
+            (((i as u128) << 64) - 1) / nb_shards as u128 + 1
+        }
+    };
+
+    (start(shard) << 64, (start(shard + 1) << 64).wrapping_sub(1))
+}
+
+/// Output path of each shard, {shard} in output is replaced by shard number
+pub fn paths(output: &str, nb_shards: usize) -> Result<Vec<String>> {
+    if !output.contains("{shard}") {
+        return Err(Error::CliShardOutputTemplate.into());
+    }
+
+    Ok((0..nb_shards)
+        .map(|shard| output.replace("{shard}", &shard.to_string()))
+        .collect())
+}
+
+/// Path of manifest, {shard} in output is replaced by manifest and extension by tsv
+pub fn manifest_path(output: &str) -> String {
+    std::path::Path::new(&output.replace("{shard}", "manifest"))
+        .with_extension("tsv")
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Split bucket of kmers with multiple minimizer by shard of their minimizer
+pub fn split(
+    bucket: &bucket::Bucket,
+    k: u8,
+    m: u8,
+    nb_shards: usize,
+    encoding: seq2bits::Encoding,
+) -> Vec<bucket::Bucket> {
+    let mut parts = vec![bucket::Bucket::default(); nb_shards];
+
+    for (kmer, counts) in bucket.iter() {
+        let (minimizer, _, _) = encoding.get_minimizer(*kmer, k, m);
+
+        parts[shard(minimizer, nb_shards)].insert(*kmer, counts.clone());
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTG: seq2bits::Encoding = seq2bits::Encoding::ACTG;
+
+    #[test]
+    fn shard_() {
+        for minimizer in 0..1000 {
+            let shard = shard(minimizer, 3);
+            let (first, last) = range(shard, 3);
+            let hash = seq2bits::hash(minimizer);
+
+            assert!(shard < 3);
+            assert!(first <= hash && hash <= last);
+        }
+
+        assert_eq!(shard(ACTG.seq2bit(b"ACGTA"), 1), 0);
+    }
+
+    #[test]
+    fn range_() {
+        assert_eq!(range(0, 1), (0, u128::MAX));
+
+        assert_eq!(range(0, 2), (0, (1 << 127) - 1));
+        assert_eq!(range(1, 2), (1 << 127, u128::MAX));
+
+        let (_, last) = range(0, 3);
+        let (first, _) = range(1, 3);
+        assert_eq!(last + 1, first);
+    }
+
+    #[test]
+    fn paths_() {
+        assert_eq!(
+            paths("out.{shard}.kff", 2).unwrap(),
+            vec!["out.0.kff".to_string(), "out.1.kff".to_string()]
+        );
+        assert!(paths("out.kff", 2).is_err());
+
+        assert_eq!(manifest_path("out.{shard}.kff"), "out.manifest.tsv");
+    }
+
+    #[test]
+    fn split_() {
+        let mut bucket = bucket::Bucket::default();
+        for kmer in &[b"ACGTACGTA", b"TTGACCAGT", b"GGATCCATG"] {
+            bucket.insert(ACTG.seq2bit(*kmer), vec![1]);
+        }
+
+        let parts = split(&bucket, 9, 3, 4, ACTG);
+
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts.iter().map(|part| part.len()).sum::<usize>(), 3);
+        for (i, part) in parts.iter().enumerate() {
+            for kmer in part.keys() {
+                assert_eq!(shard(ACTG.get_minimizer(*kmer, 9, 3).0, 4), i);
+            }
+        }
+    }
+}
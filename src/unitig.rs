@@ -0,0 +1,186 @@
+//! Decompose unitigs (BCALM style FASTA) in kmers with abundance.
+//!
+//! Abundance is read in header, `ab:Z:` give one abundance by kmer of unitig, otherwise `km:f:` mean abundance (rounded) is assign to all kmers of unitig. Kmers with a non ACGT nucleotide are ignored.
+
+/* crate use */
+use anyhow::Result;
+
+/* local use */
+use crate::error::Error;
+use crate::fastx;
+use crate::seq2bits;
+
+/// Abundance of kmers of a unitig
+#[derive(Debug, Clone, PartialEq)]
+pub enum Abundance {
+    Mean(u64),
+    PerKmer(Vec<u64>),
+}
+
+/// Parse abundance in unitig header, `ab:Z:` is prefer to `km:f:`
+pub fn abundance(header: &[u8]) -> Option<Abundance> {
+    let header = std::str::from_utf8(header).ok()?;
+    let mut tokens = header.split_ascii_whitespace();
+
+    let mut mean = None;
+    while let Some(token) = tokens.next() {
+        if let Some(value) = token.strip_prefix("ab:Z:") {
+            let mut abundances = Vec::new();
+            if !value.is_empty() {
+                abundances.push(value.parse::<u64>().ok()?);
+            }
+            abundances.extend(
+                tokens
+                    .map(|x| x.parse::<u64>())
+                    .take_while(|x| x.is_ok())
+                    .filter_map(|x| x.ok()),
+            );
+
+            return Some(Abundance::PerKmer(abundances));
+        } else if let Some(value) = token.strip_prefix("km:f:") {
+            mean = value.parse::<f64>().ok().map(|x| x.round() as u64);
+        }
+    }
+
+    mean.map(Abundance::Mean)
+}
+
+/// Kmers and abundance of a unitig
+pub fn decompose(
+    record: &fastx::Record,
+    k: u8,
+    encoding: seq2bits::Encoding,
+) -> Result<Vec<(u128, u64)>> {
+    let abundance = abundance(&record.header).ok_or_else(|| {
+        Error::UnitigMissingAbundance(String::from_utf8_lossy(&record.header).to_string())
+    })?;
+
+    let nb_kmers = (record.seq.len() + 1).saturating_sub(k as usize);
+    if let Abundance::PerKmer(abundances) = &abundance {
+        if abundances.len() != nb_kmers {
+            return Err(Error::UnitigAbundanceLength {
+                header: String::from_utf8_lossy(&record.header).to_string(),
+                expected: nb_kmers,
+                found: abundances.len(),
+            }
+            .into());
+        }
+    }
+
+    Ok(split(&record.seq, k, &abundance, encoding))
+}
+
+/// Kmers of a sequence with abundance, [Abundance::PerKmer] must contains one abundance by kmer
+pub fn split(
+    seq: &[u8],
+    k: u8,
+    abundance: &Abundance,
+    encoding: seq2bits::Encoding,
+) -> Vec<(u128, u64)> {
+    let mask = if k == 64 {
+        u128::MAX
+    } else {
+        (1 << (k as u32 * 2)) - 1
+    };
+
+    let mut kmers = Vec::with_capacity((seq.len() + 1).saturating_sub(k as usize));
+    let mut kmer: u128 = 0;
+    let mut length: usize = 0;
+    for (i, nuc) in seq.iter().enumerate() {
+        if seq2bits::is_valid(*nuc) {
+            kmer = ((kmer << 2) | encoding.nuc2bit(*nuc)) & mask;
+            length += 1;
+
+            if length >= k as usize {
+                let count = match abundance {
+                    Abundance::Mean(mean) => *mean,
+                    Abundance::PerKmer(abundances) => abundances[i + 1 - k as usize],
+                };
+                kmers.push((kmer, count));
+            }
+        } else {
+            length = 0;
+        }
+    }
+
+    kmers
+}
+
+/// Kmers and abundance of all unitigs
+pub fn kmers<I>(
+    records: I,
+    k: u8,
+    encoding: seq2bits::Encoding,
+) -> impl Iterator<Item = Result<(u128, u64)>>
+where
+    I: Iterator<Item = Result<fastx::Record>>,
+{
+    records.flat_map(move |record| {
+        match record.and_then(|record| decompose(&record, k, encoding)) {
+            Ok(kmers) => kmers.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(e) => vec![Err(e)],
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ACTG: seq2bits::Encoding = seq2bits::Encoding::ACTG;
+
+    fn record(header: &[u8], seq: &[u8]) -> fastx::Record {
+        fastx::Record {
+            header: header.to_vec(),
+            seq: seq.to_vec(),
+        }
+    }
+
+    #[test]
+    fn abundance_() {
+        assert_eq!(
+            abundance(b"0 LN:i:7 KC:i:9 km:f:2.6 L:+:1:-"),
+            Some(Abundance::Mean(3))
+        );
+        assert_eq!(
+            abundance(b"0 LN:i:7 ab:Z:2 3 4 L:+:1:-"),
+            Some(Abundance::PerKmer(vec![2, 3, 4]))
+        );
+        assert_eq!(abundance(b"0 LN:i:7"), None);
+    }
+
+    #[test]
+    fn mean() {
+        assert_eq!(
+            decompose(&record(b"0 km:f:2.0", b"ACGTTAC"), 5, ACTG).unwrap(),
+            vec![
+                (ACTG.seq2bit(b"ACGTT"), 2),
+                (ACTG.seq2bit(b"CGTTA"), 2),
+                (ACTG.seq2bit(b"GTTAC"), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn per_kmer() {
+        assert_eq!(
+            decompose(&record(b"0 ab:Z:2 3 4", b"ACGTTAC"), 5, ACTG).unwrap(),
+            vec![
+                (ACTG.seq2bit(b"ACGTT"), 2),
+                (ACTG.seq2bit(b"CGTTA"), 3),
+                (ACTG.seq2bit(b"GTTAC"), 4),
+            ]
+        );
+
+        assert!(decompose(&record(b"0 ab:Z:2 3", b"ACGTTAC"), 5, ACTG).is_err());
+    }
+
+    #[test]
+    fn missing_abundance() {
+        assert!(
+            kmers(vec![Ok(record(b"0", b"ACGTTAC"))].into_iter(), 5, ACTG)
+                .collect::<Result<Vec<_>>>()
+                .is_err()
+        );
+    }
+}
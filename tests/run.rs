@@ -100,21 +100,85 @@ fn read_kff(path: &str) -> Vec<(u128, u8)> {
     res
 }
 
-#[test]
-fn all_kmer_is_present() {
+fn read_kmer_list_u32(path: &str) -> Vec<(u128, Vec<u8>)> {
+    let input = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b',')
+        .has_headers(false)
+        .from_reader(input);
+    let mut iter = reader.records();
+
+    let mut res = Vec::new();
+
+    while let Some(Ok(record)) = iter.next() {
+        let cano = canonical(seq2bit(record[0].as_bytes()), 11).0;
+
+        res.push((
+            cano,
+            u32::from_str(&record[1]).unwrap().to_le_bytes().to_vec(),
+        ));
+    }
+
+    res.sort();
+
+    res
+}
+
+fn read_matrix(path: &str) -> Vec<(u128, Vec<u8>)> {
+    let input = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b',')
+        .has_headers(true)
+        .from_reader(input);
+    let mut iter = reader.records();
+
+    let mut res = Vec::new();
+
+    while let Some(Ok(record)) = iter.next() {
+        let cano = canonical(seq2bit(record[0].as_bytes()), 11).0;
+
+        res.push((
+            cano,
+            record
+                .iter()
+                .skip(1)
+                .map(|x| u8::from_str(x).unwrap())
+                .collect(),
+        ));
+    }
+
+    res.sort();
+
+    res
+}
+
+fn read_kff_data(path: &str) -> Vec<(u128, Vec<u8>)> {
+    let mut input = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+
+    let mut reader = kff::Reader::new(&mut input).unwrap();
+    let rev_encoding = reader.rev_encoding();
+
+    let mut res = Vec::new();
+
+    while let Ok(section) = reader.next_section() {
+        let mut it = section.into_iter();
+        while let Some(Ok(kmer)) = it.next() {
+            let cano = canonical(seq2bit(&kmer.seq().into_nuc(rev_encoding)), 11).0;
+
+            res.push((cano, kmer.data().to_vec()));
+        }
+    }
+
+    res.sort();
+
+    res
+}
+
+fn run_kmers2kff(args: &[&str]) {
     let mut child = Command::new("./target/debug/kmers2kff")
-        .args(&[
-            "-i",
-            "tests/data/test.csv",
-            "-o",
-            "tests/test.kff",
-            "-k",
-            "11",
-            "-m",
-            "6",
-            "-p",
-            "tests",
-        ])
+        .args(args)
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()
@@ -131,9 +195,681 @@ fn all_kmer_is_present() {
         println!("stderr: {}", stderr);
         panic!();
     }
+}
+
+#[test]
+fn all_kmer_is_present() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-o",
+        "tests/test.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+    ]);
 
     assert_eq!(
         read_kff("tests/test.kff"),
         read_kmer_list("tests/data/test.csv")
     );
 }
+
+#[test]
+fn compressed_input() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv.gz",
+        "-o",
+        "tests/test_gz.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+    ]);
+
+    assert_eq!(
+        read_kff("tests/test_gz.kff"),
+        read_kmer_list("tests/data/test.csv")
+    );
+}
+
+#[test]
+fn matrix_input() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/matrix.csv",
+        "-o",
+        "tests/test_matrix.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "-f",
+        "matrix",
+        "--header",
+        "--kmer-column",
+        "kmer",
+    ]);
+
+    assert_eq!(
+        read_kff_data("tests/test_matrix.kff"),
+        read_matrix("tests/data/matrix.csv")
+    );
+}
+
+#[test]
+fn merge_inputs() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-i",
+        "tests/data/test.csv.gz",
+        "-o",
+        "tests/test_merge_sum.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+    ]);
+
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-i",
+        "tests/data/test.csv.gz",
+        "-o",
+        "tests/test_merge_samples.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--merge",
+        "samples",
+    ]);
+
+    let truth = read_kmer_list("tests/data/test.csv");
+
+    assert_eq!(
+        read_kff_data("tests/test_merge_sum.kff"),
+        truth
+            .iter()
+            .map(|(kmer, count)| (*kmer, vec![count * 2]))
+            .collect::<Vec<(u128, Vec<u8>)>>()
+    );
+    assert_eq!(
+        read_kff_data("tests/test_merge_samples.kff"),
+        truth
+            .iter()
+            .map(|(kmer, count)| (*kmer, vec![*count, *count]))
+            .collect::<Vec<(u128, Vec<u8>)>>()
+    );
+}
+
+#[test]
+fn batch() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-i",
+        "tests/data/large_count.csv",
+        "-o",
+        "tests/test_batch_{name}.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--overflow",
+        "auto-widen",
+        "--batch",
+    ]);
+
+    assert_eq!(
+        read_kff("tests/test_batch_test.kff"),
+        read_kmer_list("tests/data/test.csv")
+    );
+    assert_eq!(
+        read_kff_data("tests/test_batch_large_count.kff"),
+        read_kmer_list_u32("tests/data/large_count.csv")
+    );
+}
+
+/// Number of kmers and buckets of each shard in a manifest
+fn read_manifest(path: &str) -> Vec<(u64, u64)> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            (
+                u64::from_str(fields[4]).unwrap(),
+                u64::from_str(fields[5]).unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn batch_same_output() {
+    // test.csv and test.csv.gz have the same name
+    let status = Command::new("./target/debug/kmers2kff")
+        .args(&[
+            "-i",
+            "tests/data/test.csv",
+            "-i",
+            "tests/data/test.csv.gz",
+            "-o",
+            "tests/test_batch_same_{name}.kff",
+            "-k",
+            "11",
+            "-m",
+            "6",
+            "-p",
+            "tests",
+            "--batch",
+        ])
+        .stderr(Stdio::null())
+        .stdout(Stdio::null())
+        .status()
+        .expect("Couldn't create kmers2kff subprocess");
+
+    assert!(!status.success());
+    assert!(!std::path::Path::new("tests/test_batch_same_test.kff").exists());
+}
+
+#[test]
+fn streaming() {
+    for (output, streaming) in &[
+        ("tests/test_streaming.{shard}.kff", true),
+        ("tests/test_streaming_memory.{shard}.kff", false),
+    ] {
+        let mut args = vec![
+            "-i",
+            "tests/data/sorted_matrix.csv",
+            "-o",
+            output,
+            "-k",
+            "11",
+            "-m",
+            "6",
+            "-p",
+            "tests",
+            "-f",
+            "matrix",
+            "--header",
+            "--kmer-column",
+            "kmer",
+            "--shards",
+            "2",
+        ];
+        if *streaming {
+            args.extend(&["--streaming", "--buffer-size", "100"]);
+        }
+
+        run_kmers2kff(&args);
+    }
+
+    let mut kmers = read_kff_data("tests/test_streaming.0.kff");
+    kmers.extend(read_kff_data("tests/test_streaming.1.kff"));
+    kmers.sort();
+    assert_eq!(kmers, read_matrix("tests/data/matrix.csv"));
+
+    // each window of 100 kmers is written in its own sections, a minimizer could have a section in many windows
+    let streaming = read_manifest("tests/test_streaming.manifest.tsv");
+    let memory = read_manifest("tests/test_streaming_memory.manifest.tsv");
+    for ((kmers, buckets), (memory_kmers, memory_buckets)) in streaming.iter().zip(memory.iter()) {
+        assert_eq!(kmers, memory_kmers);
+        assert!(buckets >= memory_buckets);
+    }
+    assert!(
+        streaming.iter().map(|(_, buckets)| buckets).sum::<u64>()
+            > memory.iter().map(|(_, buckets)| buckets).sum::<u64>()
+    );
+
+    // crc32 of input is computed while it's read, header is written before in streaming mode
+    let mut crc = flate2::Crc::new();
+    crc.update(&std::fs::read("tests/data/sorted_matrix.csv").unwrap());
+    for (path, expected) in &[
+        ("tests/test_streaming.0.kff", None),
+        (
+            "tests/test_streaming_memory.0.kff",
+            Some(format!("input_crc32={:08x}", crc.sum())),
+        ),
+    ] {
+        let mut input = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+        let reader = kff::Reader::new(&mut input).unwrap();
+        let metadata = String::from_utf8_lossy(reader.metadata()).to_string();
+
+        assert_eq!(
+            metadata
+                .lines()
+                .find(|line| line.starts_with("input_crc32="))
+                .map(|line| line.to_string()),
+            *expected
+        );
+    }
+}
+
+#[test]
+fn streaming_many_inputs() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/sorted.csv",
+        "-i",
+        "tests/data/sorted.csv",
+        "-o",
+        "tests/test_streaming_inputs.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--merge",
+        "samples",
+        "--streaming",
+        "--buffer-size",
+        "100",
+    ]);
+
+    assert_eq!(
+        read_kff_data("tests/test_streaming_inputs.kff"),
+        read_kmer_list("tests/data/test.csv")
+            .into_iter()
+            .map(|(kmer, count)| (kmer, vec![count, count]))
+            .collect::<Vec<(u128, Vec<u8>)>>()
+    );
+}
+
+#[test]
+fn streaming_unsorted() {
+    // test.csv isn't sorted in lexicographic order
+    for args in &[
+        &["-i", "tests/data/test.csv"][..],
+        &["-i", "tests/data/sorted.csv", "--overflow", "auto-widen"][..],
+    ] {
+        let status = Command::new("./target/debug/kmers2kff")
+            .args(*args)
+            .args(&[
+                "-o",
+                "tests/test_streaming_unsorted.kff",
+                "-k",
+                "11",
+                "-m",
+                "6",
+                "-p",
+                "tests",
+                "--streaming",
+            ])
+            .stderr(Stdio::null())
+            .status()
+            .expect("Couldn't create kmers2kff subprocess");
+        assert!(!status.success());
+    }
+}
+
+#[test]
+fn index() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-o",
+        "tests/test_index.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--index",
+    ]);
+
+    // index, minimizer table and footer doesn't change kmers read by a kff reader
+    assert_eq!(
+        read_kff("tests/test_index.kff"),
+        read_kmer_list("tests/data/test.csv")
+    );
+
+    let mut input = std::io::BufReader::new(std::fs::File::open("tests/test_index.kff").unwrap());
+    let mut reader = kff::Reader::new(&mut input).unwrap();
+    while reader.next_section().is_ok() {}
+    let mut minimizers = reader
+        .variables()
+        .iter()
+        .filter(|(name, _)| name.starts_with("minimizer_"))
+        .map(|(name, position)| (*position, name.clone()))
+        .collect::<Vec<(u64, String)>>();
+    minimizers.sort_unstable();
+    assert!(!minimizers.is_empty());
+
+    let data = std::fs::read("tests/test_index.kff").unwrap();
+    let u64_at = |pos: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&data[pos..pos + 8]);
+        u64::from_be_bytes(bytes)
+    };
+
+    // footer: 'v', number of variables, first_index, nb_kmers and footer_size
+    let footer = data.len() - u64_at(data.len() - 8) as usize;
+    assert_eq!(data[footer], b'v');
+    assert_eq!(&data[footer + 9..footer + 21], b"first_index\0");
+    assert_eq!(&data[footer + 29..footer + 38], b"nb_kmers\0");
+    assert_eq!(u64_at(footer + 38), 1396);
+
+    let first_index = u64_at(footer + 21) as usize;
+    assert_eq!(data[first_index], b'i');
+    let nb_sections = u64_at(first_index + 1) as usize;
+
+    // each entry is a type and a position relative to end of index section
+    let index_end = (first_index + 1 + 8 + nb_sections * 9 + 8) as i64;
+    assert_eq!(u64_at(index_end as usize - 8), 0);
+    let entries = (0..nb_sections)
+        .map(|i| {
+            let pos = first_index + 9 + i * 9;
+            (data[pos], (index_end + u64_at(pos + 1) as i64) as u64)
+        })
+        .collect::<Vec<(u8, u64)>>();
+
+    // minimizer table points to minimizer sections and is the last indexed section
+    assert_eq!(
+        entries
+            .iter()
+            .filter(|(section_type, _)| *section_type == b'm')
+            .map(|(_, position)| *position)
+            .collect::<Vec<u64>>(),
+        minimizers
+            .iter()
+            .map(|(position, _)| *position)
+            .collect::<Vec<u64>>()
+    );
+    assert_eq!(entries.last().unwrap().0, b'v');
+    assert!(entries
+        .iter()
+        .all(|(_, position)| 0 < *position && *position < first_index as u64));
+
+    // minimizer sections are written by increasing minimizer, each one once
+    let names = minimizers
+        .iter()
+        .map(|(_, name)| name.clone())
+        .collect::<Vec<String>>();
+    assert!(names.iter().all(|name| name.ends_with("_0")));
+    assert!(names.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn presence_only() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-o",
+        "tests/test_presence.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--presence-only",
+    ]);
+
+    assert_eq!(
+        read_kff_data("tests/test_presence.kff"),
+        read_kmer_list("tests/data/test.csv")
+            .into_iter()
+            .map(|(kmer, _)| (kmer, Vec::new()))
+            .collect::<Vec<(u128, Vec<u8>)>>()
+    );
+}
+
+#[test]
+fn encoding() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-o",
+        "tests/test_encoding.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--encoding",
+        "ACTG",
+    ]);
+
+    let mut input =
+        std::io::BufReader::new(std::fs::File::open("tests/test_encoding.kff").unwrap());
+    assert_eq!(kff::Reader::new(&mut input).unwrap().encoding(), 0b00011110);
+
+    assert_eq!(
+        read_kff("tests/test_encoding.kff"),
+        read_kmer_list("tests/data/test.csv")
+    );
+
+    // default encoding is the one of previous versions
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-o",
+        "tests/test_encoding_default.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+    ]);
+
+    let mut input =
+        std::io::BufReader::new(std::fs::File::open("tests/test_encoding_default.kff").unwrap());
+    assert_eq!(kff::Reader::new(&mut input).unwrap().encoding(), 0b00011011);
+}
+
+#[test]
+fn stdout_output() {
+    let output = Command::new("./target/debug/kmers2kff")
+        .args(&[
+            "-i",
+            "tests/data/test.csv",
+            "-o",
+            "-",
+            "-k",
+            "11",
+            "-m",
+            "6",
+            "-p",
+            "tests",
+        ])
+        .output()
+        .expect("Couldn't create kmers2kff subprocess");
+    assert!(output.status.success());
+
+    std::fs::write("tests/test_stdout.kff", output.stdout).unwrap();
+
+    assert_eq!(
+        read_kff("tests/test_stdout.kff"),
+        read_kmer_list("tests/data/test.csv")
+    );
+}
+
+#[test]
+fn shards() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/test.csv",
+        "-o",
+        "tests/test_shard.{shard}.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--shards",
+        "3",
+    ]);
+
+    let mut kmers = Vec::new();
+    for shard in 0..3 {
+        kmers.extend(read_kff(&format!("tests/test_shard.{}.kff", shard)));
+    }
+    kmers.sort();
+
+    assert_eq!(kmers, read_kmer_list("tests/data/test.csv"));
+
+    let manifest = std::fs::read_to_string("tests/test_shard.manifest.tsv").unwrap();
+    let lines = manifest.lines().collect::<Vec<&str>>();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("0\ttests/test_shard.0.kff\t00000000000000000000000000000000\t"));
+    assert!(lines[3].contains("\tffffffffffffffffffffffffffffffff\t"));
+}
+
+#[test]
+fn reencode_kff() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/matrix.csv",
+        "-o",
+        "tests/test_reencode_input.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "-f",
+        "matrix",
+        "--header",
+        "--kmer-column",
+        "kmer",
+    ]);
+
+    run_kmers2kff(&[
+        "-i",
+        "tests/test_reencode_input.kff",
+        "-o",
+        "tests/test_reencode.kff",
+        "-m",
+        "4",
+        "-p",
+        "tests",
+        "-f",
+        "kff",
+    ]);
+
+    assert_eq!(
+        read_kff_data("tests/test_reencode.kff"),
+        read_matrix("tests/data/matrix.csv")
+    );
+
+    // sample names of input are kept
+    let mut input =
+        std::io::BufReader::new(std::fs::File::open("tests/test_reencode.kff").unwrap());
+    let reader = kff::Reader::new(&mut input).unwrap();
+    let metadata = String::from_utf8_lossy(reader.metadata()).to_string();
+    assert_eq!(
+        metadata
+            .lines()
+            .filter(|line| line.starts_with("sample="))
+            .collect::<Vec<&str>>(),
+        vec!["sample=s1", "sample=s2", "sample=s3"]
+    );
+}
+
+#[test]
+fn reencode_kff_count_width() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/large_count.csv",
+        "-o",
+        "tests/test_reencode_width_input.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--count-width",
+        "4",
+    ]);
+
+    run_kmers2kff(&[
+        "-i",
+        "tests/test_reencode_width_input.kff",
+        "-o",
+        "tests/test_reencode_width.kff",
+        "-m",
+        "4",
+        "-p",
+        "tests",
+        "-f",
+        "kff",
+        "--count-width",
+        "4",
+    ]);
+
+    assert_eq!(
+        read_kff_data("tests/test_reencode_width.kff"),
+        read_kmer_list_u32("tests/data/large_count.csv")
+    );
+
+    // truncated kff input must fail
+    let data = std::fs::read("tests/test_reencode_width_input.kff").unwrap();
+    std::fs::write("tests/test_truncated.kff", &data[..data.len() - 5]).unwrap();
+
+    let status = Command::new("./target/debug/kmers2kff")
+        .args(&[
+            "-i",
+            "tests/test_truncated.kff",
+            "-o",
+            "tests/test_truncated_output.kff",
+            "-m",
+            "4",
+            "-p",
+            "tests",
+            "-f",
+            "kff",
+        ])
+        .stderr(Stdio::null())
+        .status()
+        .expect("Couldn't create kmers2kff subprocess");
+    assert!(!status.success());
+}
+
+#[test]
+fn auto_widen_count() {
+    run_kmers2kff(&[
+        "-i",
+        "tests/data/large_count.csv",
+        "-o",
+        "tests/test_large_count.kff",
+        "-k",
+        "11",
+        "-m",
+        "6",
+        "-p",
+        "tests",
+        "--overflow",
+        "auto-widen",
+    ]);
+
+    assert_eq!(
+        read_kff_data("tests/test_large_count.kff"),
+        read_kmer_list_u32("tests/data/large_count.csv")
+    );
+}